@@ -1,9 +1,12 @@
-use std::{fmt, str::FromStr};
+use std::{fmt, io::Write, str::FromStr};
 
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
-use crate::CmdExector;
+use crate::{
+    get_reader, get_writer, process_decode, process_encode, process_encode_data_uri, CmdExector,
+    MIME_LINE_WIDTH, PEM_LINE_WIDTH,
+};
 
 use super::verify_file;
 
@@ -20,35 +23,79 @@ pub enum Base64SubCommand {
 pub struct Base64EncodeOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
     #[arg(long, value_parser = parse_base64_format, default_value = "standard")]
     pub format: Base64Format,
+    /// wrap lines at "mime" (76), "pem" (64) or any number of columns
+    #[arg(long, value_parser = parse_wrap)]
+    pub wrap: Option<usize>,
+    /// output a data:<mime>;base64,... uri
+    #[arg(long, conflicts_with_all = ["format", "wrap"])]
+    pub data_uri: bool,
+    /// mime type of the data uri, sniffed from the content if omitted
+    #[arg(long, requires = "data_uri")]
+    pub mime: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct Base64DecodeOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
+    /// decoded bytes are written as is, use --text to print them as a string
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// "auto" guesses the format from the input
     #[arg(long, value_parser = parse_base64_format, default_value = "standard")]
     pub format: Base64Format,
+    /// accept input with or without padding
+    #[arg(long)]
+    pub lenient: bool,
+    #[arg(long)]
+    pub text: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum Base64Format {
+    // RFC 4648 alphabet, padded
     Standard,
+    StandardNoPad,
+    // RFC 4648 url and filename safe alphabet, unpadded
     UrlSafe,
+    UrlSafePad,
+    Bcrypt,
+    Crypt,
+    // IMAP modified UTF-7 (RFC 3501)
+    Imap,
+    // decode only, detect one of the above
+    Auto,
 }
 
 fn parse_base64_format(format: &str) -> Result<Base64Format, anyhow::Error> {
     format.parse()
 }
 
+fn parse_wrap(wrap: &str) -> Result<usize, anyhow::Error> {
+    match wrap {
+        "mime" => Ok(MIME_LINE_WIDTH),
+        "pem" => Ok(PEM_LINE_WIDTH),
+        _ => Ok(wrap.parse()?),
+    }
+}
+
 impl FromStr for Base64Format {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "standard" => Ok(Base64Format::Standard),
-            "urlsafe" => Ok(Base64Format::UrlSafe),
+            "standard" | "standard-pad" => Ok(Base64Format::Standard),
+            "standard-nopad" => Ok(Base64Format::StandardNoPad),
+            "urlsafe" | "urlsafe-nopad" => Ok(Base64Format::UrlSafe),
+            "urlsafe-pad" => Ok(Base64Format::UrlSafePad),
+            "bcrypt" => Ok(Base64Format::Bcrypt),
+            "crypt" => Ok(Base64Format::Crypt),
+            "imap" => Ok(Base64Format::Imap),
+            "auto" => Ok(Base64Format::Auto),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
@@ -58,7 +105,13 @@ impl From<Base64Format> for &'static str {
     fn from(format: Base64Format) -> Self {
         match format {
             Base64Format::Standard => "standard",
+            Base64Format::StandardNoPad => "standard-nopad",
             Base64Format::UrlSafe => "urlsafe",
+            Base64Format::UrlSafePad => "urlsafe-pad",
+            Base64Format::Bcrypt => "bcrypt",
+            Base64Format::Crypt => "crypt",
+            Base64Format::Imap => "imap",
+            Base64Format::Auto => "auto",
         }
     }
 }
@@ -71,18 +124,31 @@ impl fmt::Display for Base64Format {
 
 impl CmdExector for Base64EncodeOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let mut reader = crate::get_reader(&self.input)?;
-        let ret = crate::process_encode(&mut reader, self.format)?;
-        println!("{}", ret);
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        if self.data_uri {
+            process_encode_data_uri(&mut reader, &mut writer, self.mime.as_deref())?;
+        } else {
+            process_encode(&mut reader, &mut writer, self.format, self.wrap)?;
+        }
+        writeln!(writer)?;
+        writer.flush()?;
         Ok(())
     }
 }
 
 impl CmdExector for Base64DecodeOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let mut reader = crate::get_reader(&self.input)?;
-        let ret = crate::process_decode(&mut reader, self.format)?;
-        println!("{}", ret);
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        if self.text {
+            let mut buf = Vec::new();
+            process_decode(&mut reader, &mut buf, self.format, self.lenient)?;
+            writeln!(writer, "{}", String::from_utf8(buf)?)?;
+        } else {
+            process_decode(&mut reader, &mut writer, self.format, self.lenient)?;
+        }
+        writer.flush()?;
         Ok(())
     }
 }
@@ -0,0 +1,133 @@
+use crate::{
+    get_reader, get_writer, process_codec_decode, process_codec_detect, process_codec_encode,
+    CmdExector,
+};
+
+use super::verify_file;
+use clap::Parser;
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+#[derive(Debug, Parser)]
+pub struct EncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = parse_codec, default_value = "hex")]
+    pub codec: Codec,
+}
+
+#[derive(Debug, Parser)]
+pub struct DecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// decoded bytes are written as is
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = parse_codec, default_value = "hex")]
+    pub codec: Codec,
+    /// guess the codec (hex, base32, base64 or urlsafe) from the input
+    #[arg(long, conflicts_with = "codec")]
+    pub detect: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Codec {
+    Hex,
+    Base32,
+    Base32Hex,
+    ZBase32,
+    Base58,
+    Base58Check,
+    Ascii85,
+    Z85,
+    Base64,
+    UrlSafe,
+}
+
+fn parse_codec(codec: &str) -> Result<Codec, anyhow::Error> {
+    codec.parse()
+}
+
+impl FromStr for Codec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(Codec::Hex),
+            "base32" => Ok(Codec::Base32),
+            "base32hex" => Ok(Codec::Base32Hex),
+            "zbase32" => Ok(Codec::ZBase32),
+            "base58" => Ok(Codec::Base58),
+            "base58check" => Ok(Codec::Base58Check),
+            "ascii85" => Ok(Codec::Ascii85),
+            "z85" => Ok(Codec::Z85),
+            "base64" => Ok(Codec::Base64),
+            "urlsafe" => Ok(Codec::UrlSafe),
+            _ => Err(anyhow::anyhow!("Invalid codec")),
+        }
+    }
+}
+
+impl From<Codec> for &'static str {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::Hex => "hex",
+            Codec::Base32 => "base32",
+            Codec::Base32Hex => "base32hex",
+            Codec::ZBase32 => "zbase32",
+            Codec::Base58 => "base58",
+            Codec::Base58Check => "base58check",
+            Codec::Ascii85 => "ascii85",
+            Codec::Z85 => "z85",
+            Codec::Base64 => "base64",
+            Codec::UrlSafe => "urlsafe",
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExector for EncodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        process_codec_encode(&mut reader, &mut writer, self.codec)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExector for DecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        if self.detect {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            let candidates = process_codec_detect(&buf);
+            for (codec, confidence) in &candidates {
+                eprintln!("{}: {:.1}%", codec, confidence * 100.0);
+            }
+            let (codec, _) = candidates
+                .first()
+                .ok_or_else(|| anyhow::anyhow!("Could not detect the encoding"))?;
+            eprintln!("Decoding as {}", codec);
+            process_codec_decode(&mut &buf[..], &mut writer, *codec)?;
+            writer.flush()?;
+            return Ok(());
+        }
+        process_codec_decode(&mut reader, &mut writer, self.codec)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -1,11 +1,28 @@
-use crate::CmdExector;
+use crate::{
+    get_content, get_reader, process_genbytes, process_genpass, process_genpass_check,
+    process_genpass_derive_rng, CmdExector,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use clap::Parser;
-use zxcvbn::zxcvbn;
+use data_encoding::HEXLOWER;
+use enum_dispatch::enum_dispatch;
+use rand::{CryptoRng, RngCore};
+use std::{
+    fmt,
+    io::{BufRead, BufReader},
+    str::FromStr,
+};
+
+use super::verify_file;
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+
     #[arg(short, long, default_value_t = 16)]
-    pub length: u8,
+    pub length: usize,
 
     #[arg(long, default_value_t = true)]
     pub uppercase: bool,
@@ -18,22 +35,183 @@ pub struct GenPassOpts {
 
     #[arg(long, default_value_t = true)]
     pub symbol: bool,
+
+    /// keep regenerating until zxcvbn scores the password at least this
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+
+    /// derive a site specific password from a master key instead of random
+    #[arg(long, requires_all = ["master_key", "site"])]
+    pub derive: bool,
+
+    #[arg(long, value_parser = verify_file, requires = "derive")]
+    pub master_key: Option<String>,
+
+    #[arg(long, requires = "derive")]
+    pub site: Option<String>,
+
+    /// bump this to rotate a derived password
+    #[arg(long, default_value_t = 1, requires = "derive")]
+    pub counter: u32,
+
+    /// generate this many raw random bytes instead of a password
+    #[arg(long)]
+    pub bytes: Option<usize>,
+
+    #[arg(long, value_parser = parse_bytes_encoding, default_value = "hex", requires = "bytes")]
+    pub encoding: BytesEncoding,
 }
 
-impl CmdExector for GenPassOpts {
-    async fn execute(self) -> anyhow::Result<()> {
-        let ret = crate::process_genpass(
+#[derive(Debug, Clone, Copy)]
+pub enum BytesEncoding {
+    Hex,
+    Base64,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum GenPassSubCommand {
+    #[command(about = "Check the strength of passwords read line by line")]
+    Check(GenPassCheckOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// user related words (username, email, etc.) to penalize
+    #[arg(short, long = "user-input")]
+    pub user_inputs: Vec<String>,
+
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+}
+
+fn parse_bytes_encoding(encoding: &str) -> Result<BytesEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+impl FromStr for BytesEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(BytesEncoding::Hex),
+            "base64" => Ok(BytesEncoding::Base64),
+            _ => Err(anyhow::anyhow!("Invalid encoding")),
+        }
+    }
+}
+
+impl From<BytesEncoding> for &'static str {
+    fn from(encoding: BytesEncoding) -> Self {
+        match encoding {
+            BytesEncoding::Hex => "hex",
+            BytesEncoding::Base64 => "base64",
+        }
+    }
+}
+
+impl fmt::Display for BytesEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl GenPassOpts {
+    fn generate<R: RngCore + CryptoRng>(&self, rng: &mut R) -> anyhow::Result<()> {
+        if let Some(length) = self.bytes {
+            let bytes = process_genbytes(rng, length);
+            let encoded = match self.encoding {
+                BytesEncoding::Hex => HEXLOWER.encode(&bytes),
+                BytesEncoding::Base64 => STANDARD.encode(&bytes),
+            };
+            println!("{}", encoded);
+            eprintln!("Entropy: {} bits", length * 8);
+            return Ok(());
+        }
+
+        let ret = process_genpass(
+            rng,
             self.length,
             self.uppercase,
             self.lowercase,
             self.number,
             self.symbol,
+            self.min_score,
         )?;
-        println!("{}", ret);
+        println!("{}", ret.password);
 
         // output password strength in stderr
-        let estimate = zxcvbn(&ret, &[])?;
-        eprintln!("Password strength: {}", estimate.score());
+        eprintln!("Password strength: {}", ret.score);
+        eprintln!("Entropy: {:.1} bits", ret.entropy);
+        Ok(())
+    }
+}
+
+impl CmdExector for GenPassOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+
+        match (&self.master_key, &self.site) {
+            (Some(master_key), Some(site)) if self.derive => {
+                let master_key = get_content(master_key)?;
+                let mut rng = process_genpass_derive_rng(&master_key, site, self.counter);
+                self.generate(&mut rng)
+            }
+            _ => self.generate(&mut rand::thread_rng()),
+        }
+    }
+}
+
+impl CmdExector for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let reader = BufReader::new(get_reader(&self.input)?);
+        let user_inputs: Vec<&str> = self.user_inputs.iter().map(|s| s.as_str()).collect();
+
+        let (mut total, mut failed) = (0, 0);
+        for (i, line) in reader.lines().enumerate() {
+            let password = line?;
+            if password.is_empty() {
+                continue;
+            }
+            total += 1;
+
+            // never echo the candidate itself, refer to it by line number
+            let ret = process_genpass_check(&password, &user_inputs)?;
+            if ret.score < self.min_score {
+                failed += 1;
+                println!(
+                    "line {}: score {}/4 (below {})",
+                    i + 1,
+                    ret.score,
+                    self.min_score
+                );
+            } else {
+                println!("line {}: score {}/4", i + 1, ret.score);
+            }
+            if let Some(warning) = ret.warning {
+                println!("  warning: {}", warning);
+            }
+            for suggestion in ret.suggestions {
+                println!("  suggestion: {}", suggestion);
+            }
+            for (scenario, time) in ret.crack_times {
+                println!("  crack time ({}): {}", scenario, time);
+            }
+        }
+
+        if failed > 0 {
+            anyhow::bail!(
+                "{} of {} password(s) scored below {}",
+                failed,
+                total,
+                self.min_score
+            );
+        }
         Ok(())
     }
 }
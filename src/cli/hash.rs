@@ -0,0 +1,172 @@
+use crate::{
+    get_content, get_reader, get_writer, process_hash, process_hash_check, process_hash_dir,
+    process_hash_manifest_diff, process_hash_merkle_root, CmdExector, HashCheckStatus,
+    HashManifest,
+};
+
+use super::{verify_file, verify_path};
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct HashOpts {
+    #[command(subcommand)]
+    pub cmd: Option<HashSubCommand>,
+
+    #[arg(short, long, default_value = "sha256", value_parser = parse_hash_algorithm)]
+    pub algorithm: HashAlgorithm,
+    /// read checksums from this file and verify them
+    #[arg(short, long, value_parser = verify_file, conflicts_with = "files")]
+    pub check: Option<String>,
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum HashSubCommand {
+    #[command(about = "Generate or verify a blake3 manifest with a merkle root of a directory")]
+    Dir(HashDirOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct HashDirOpts {
+    #[arg(value_parser = verify_path)]
+    pub dir: PathBuf,
+    /// where to write the json manifest
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// compare the directory against this manifest instead
+    #[arg(long, value_parser = verify_file, conflicts_with = "output")]
+    pub verify: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Sha512,
+    Sha1,
+    Md5,
+    Xxh3,
+}
+
+fn parse_hash_algorithm(algorithm: &str) -> Result<HashAlgorithm, anyhow::Error> {
+    algorithm.parse()
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "md5" => Ok(HashAlgorithm::Md5),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            _ => Err(anyhow::anyhow!("Invalid algorithm")),
+        }
+    }
+}
+
+impl From<HashAlgorithm> for &'static str {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExector for HashOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+
+        if let Some(check) = self.check {
+            let mut reader = get_reader(&check)?;
+            let results = process_hash_check(&mut reader, self.algorithm)?;
+            let mut failed = 0;
+            for (path, status) in results {
+                match status {
+                    HashCheckStatus::Ok => println!("{}: OK", path),
+                    HashCheckStatus::Failed => println!("{}: FAILED", path),
+                    HashCheckStatus::Unreadable => println!("{}: FAILED open or read", path),
+                }
+                if status != HashCheckStatus::Ok {
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!("{} computed checksum(s) did NOT match", failed);
+            }
+            return Ok(());
+        }
+
+        for file in &self.files {
+            let mut reader = get_reader(file)?;
+            let digest = process_hash(&mut reader, self.algorithm)?;
+            println!("{}  {}", digest, file);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for HashDirOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        // the manifest may be kept in the directory it describes
+        let manifest_path = match &self.verify {
+            Some(verify) => Some(verify.as_str()),
+            None => (self.output != "-").then_some(self.output.as_str()),
+        };
+        let manifest = process_hash_dir(&self.dir, manifest_path.map(Path::new))?;
+        let Some(verify) = self.verify else {
+            let mut writer = get_writer(&self.output)?;
+            serde_json::to_writer_pretty(&mut writer, &manifest)?;
+            writeln!(writer)?;
+            writer.flush()?;
+            eprintln!("{}", manifest.root);
+            return Ok(());
+        };
+
+        let expected: HashManifest = serde_json::from_slice(&get_content(&verify)?)?;
+        if process_hash_merkle_root(&expected.files)? != expected.root {
+            anyhow::bail!("Manifest root does not match its entries");
+        }
+        let diff = process_hash_manifest_diff(&expected, &manifest);
+        for path in &diff.added {
+            println!("added: {}", path);
+        }
+        for path in &diff.removed {
+            println!("removed: {}", path);
+        }
+        for path in &diff.modified {
+            println!("modified: {}", path);
+        }
+        if manifest.root != expected.root {
+            anyhow::bail!("Merkle root mismatch");
+        }
+        println!("✓ {}", manifest.root);
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{get_reader, process_html_escape, process_html_unescape, CmdExector};
+
+use super::verify_file;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum HtmlSubCommand {
+    #[command(about = "Escape special characters as HTML entities")]
+    Escape(HtmlEscapeOpts),
+    #[command(about = "Unescape HTML entities")]
+    Unescape(HtmlUnescapeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct HtmlEscapeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct HtmlUnescapeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+}
+
+impl CmdExector for HtmlEscapeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let ret = process_html_escape(&mut reader)?;
+        println!("{}", ret);
+        Ok(())
+    }
+}
+
+impl CmdExector for HtmlUnescapeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let ret = process_html_unescape(&mut reader)?;
+        println!("{}", ret);
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    get_content, process_jwt_decode, process_jwt_sign, process_jwt_verify, CmdExector, JwtAudience,
+    JwtClaims, JwtValidation,
+};
+
+use super::verify_file;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum JwtSubCommand {
+    #[command(about = "Sign claims into a JWT")]
+    Sign(JwtSignOpts),
+    #[command(about = "Verify the signature and claims of a JWT")]
+    Verify(JwtVerifyOpts),
+    #[command(about = "Print the header and claims of a JWT without verifying it")]
+    Decode(JwtDecodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtSignOpts {
+    /// HMAC secret for hs256, ed25519 signing key for eddsa
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    #[arg(long, default_value = "hs256", value_parser = parse_jwt_algorithm)]
+    pub alg: JwtAlgorithm,
+    #[arg(long)]
+    pub sub: Option<String>,
+    #[arg(long)]
+    pub aud: Vec<String>,
+    #[arg(long)]
+    pub iss: Option<String>,
+    /// expires after e.g. "15m", "14d"
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub exp: Option<Duration>,
+    /// not valid before now plus e.g. "1h"
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub nbf: Option<Duration>,
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtVerifyOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// HMAC secret for hs256, ed25519 public key for eddsa
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    #[arg(long, default_value = "hs256", value_parser = parse_jwt_algorithm)]
+    pub alg: JwtAlgorithm,
+    /// require the token to be meant for this audience
+    #[arg(long)]
+    pub aud: Option<String>,
+    /// require the token to be issued by this issuer
+    #[arg(long)]
+    pub iss: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtDecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum JwtAlgorithm {
+    Hs256,
+    EdDsa,
+}
+
+fn parse_jwt_algorithm(algorithm: &str) -> Result<JwtAlgorithm, anyhow::Error> {
+    algorithm.parse()
+}
+
+impl FromStr for JwtAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hs256" => Ok(JwtAlgorithm::Hs256),
+            "eddsa" => Ok(JwtAlgorithm::EdDsa),
+            _ => Err(anyhow::anyhow!("Invalid algorithm")),
+        }
+    }
+}
+
+impl From<JwtAlgorithm> for &'static str {
+    fn from(algorithm: JwtAlgorithm) -> Self {
+        // as used in the "alg" header
+        match algorithm {
+            JwtAlgorithm::Hs256 => "HS256",
+            JwtAlgorithm::EdDsa => "EdDSA",
+        }
+    }
+}
+
+impl fmt::Display for JwtAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExector for JwtSignOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = get_content(&self.key)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let aud = match self.aud.len() {
+            0 => None,
+            1 => Some(JwtAudience::One(self.aud[0].clone())),
+            _ => Some(JwtAudience::Many(self.aud)),
+        };
+        let claims = JwtClaims {
+            iss: self.iss,
+            sub: self.sub,
+            aud,
+            exp: self.exp.map(|d| now + d.as_secs()),
+            nbf: self.nbf.map(|d| now + d.as_secs()),
+            iat: Some(now),
+            ..Default::default()
+        };
+        let token = process_jwt_sign(&claims, &key, self.alg)?;
+        println!("{}", token);
+        Ok(())
+    }
+}
+
+impl CmdExector for JwtVerifyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let token = String::from_utf8(get_content(&self.input)?)?;
+        let key = get_content(&self.key)?;
+        let validation = JwtValidation {
+            aud: self.aud.as_deref(),
+            iss: self.iss.as_deref(),
+            now: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        let claims = process_jwt_verify(token.trim(), &key, self.alg, &validation)?;
+        println!("{}", serde_json::to_string_pretty(&claims)?);
+        eprintln!("✓ Token verified");
+        Ok(())
+    }
+}
+
+impl CmdExector for JwtDecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let token = String::from_utf8(get_content(&self.input)?)?;
+        let (header, claims) = process_jwt_decode(token.trim())?;
+        println!("{}", serde_json::to_string_pretty(&header)?);
+        println!("{}", serde_json::to_string_pretty(&claims)?);
+        Ok(())
+    }
+}
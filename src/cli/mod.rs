@@ -1,14 +1,24 @@
 mod base64;
+mod codec;
 mod csv;
 mod genpass;
+mod hash;
+mod html;
 mod http;
+mod jwt;
+mod otp;
+mod pem;
 mod text;
+mod url;
 
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 use std::path::{Path, PathBuf};
 
-pub use self::{base64::*, csv::*, genpass::*, http::*, text::*};
+pub use self::{
+    base64::*, codec::*, csv::*, genpass::*, hash::*, html::*, http::*, jwt::*, otp::*, pem::*,
+    text::*, url::*,
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "rcli", version, author, about, long_about = None)]
@@ -26,10 +36,32 @@ pub enum SubCommand {
     GenPass(GenPassOpts),
     #[command(subcommand, about = "Base64 encode/decode")]
     Base64(Base64SubCommand),
+    #[command(
+        name = "encode",
+        about = "Encode binary data with hex, base32, base58, base85..."
+    )]
+    Encode(EncodeOpts),
+    #[command(
+        name = "decode",
+        about = "Decode hex, base32, base58, base85... into binary data"
+    )]
+    Decode(DecodeOpts),
+    #[command(about = "Hash files or verify a checksum file")]
+    Hash(HashOpts),
+    #[command(subcommand, about = "JWT sign/verify/decode")]
+    Jwt(JwtSubCommand),
+    #[command(subcommand, about = "PEM armor wrap/unwrap")]
+    Pem(PemSubCommand),
+    #[command(subcommand, about = "URL percent encode/decode")]
+    Url(UrlSubCommand),
+    #[command(subcommand, about = "HTML entity escape/unescape")]
+    Html(HtmlSubCommand),
     #[command(subcommand, about = "Text sign/verify")]
     Text(TextSubCommand),
     #[command(subcommand, about = "HTTP server")]
     Http(HttpSubCommand),
+    #[command(subcommand, about = "TOTP/HOTP secrets and one-time codes")]
+    Otp(OtpSubCommand),
 }
 
 fn verify_file(filename: &str) -> Result<String, &'static str> {
@@ -0,0 +1,138 @@
+use crate::{
+    process_hotp, process_otp_decode_secret, process_otp_qr, process_otp_secret, process_otp_uri,
+    process_totp, CmdExector,
+};
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum OtpSubCommand {
+    #[command(about = "Generate a base32 TOTP secret and its otpauth:// uri")]
+    Secret(OtpSecretOpts),
+    #[command(about = "Compute the current TOTP code, or a HOTP code with --counter")]
+    Code(OtpCodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpSecretOpts {
+    #[arg(long)]
+    pub account: String,
+    #[arg(long, default_value = "rcli")]
+    pub issuer: String,
+    /// secret size in bytes
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    pub bytes: u64,
+    #[arg(long, value_parser = parse_otp_algorithm, default_value = "sha1")]
+    pub algorithm: OtpAlgorithm,
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(6..=10))]
+    pub digits: u32,
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub period: u64,
+    /// also render the uri as a QR code
+    #[arg(long)]
+    pub qr: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpCodeOpts {
+    #[arg(long)]
+    pub secret: String,
+    #[arg(long, value_parser = parse_otp_algorithm, default_value = "sha1")]
+    pub algorithm: OtpAlgorithm,
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(6..=10))]
+    pub digits: u32,
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub period: u64,
+    /// compute a HOTP code for this counter instead of TOTP
+    #[arg(long)]
+    pub counter: Option<u64>,
+    /// unix time to compute the TOTP code for, defaults to now
+    #[arg(long, conflicts_with = "counter")]
+    pub time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+fn parse_otp_algorithm(algorithm: &str) -> Result<OtpAlgorithm, anyhow::Error> {
+    algorithm.parse()
+}
+
+impl FromStr for OtpAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(OtpAlgorithm::Sha1),
+            "sha256" => Ok(OtpAlgorithm::Sha256),
+            "sha512" => Ok(OtpAlgorithm::Sha512),
+            _ => Err(anyhow::anyhow!("Invalid algorithm")),
+        }
+    }
+}
+
+impl From<OtpAlgorithm> for &'static str {
+    fn from(algorithm: OtpAlgorithm) -> Self {
+        match algorithm {
+            OtpAlgorithm::Sha1 => "sha1",
+            OtpAlgorithm::Sha256 => "sha256",
+            OtpAlgorithm::Sha512 => "sha512",
+        }
+    }
+}
+
+impl fmt::Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExector for OtpSecretOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let secret = process_otp_secret(&mut rand::thread_rng(), self.bytes as usize);
+        let uri = process_otp_uri(
+            &secret,
+            &self.issuer,
+            &self.account,
+            self.algorithm,
+            self.digits,
+            self.period,
+        );
+        println!("{}", secret);
+        println!("{}", uri);
+        if self.qr {
+            println!("{}", process_otp_qr(&uri)?);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for OtpCodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let secret = process_otp_decode_secret(&self.secret)?;
+        if let Some(counter) = self.counter {
+            let code = process_hotp(&secret, counter, self.digits, self.algorithm)?;
+            println!("{}", code);
+            return Ok(());
+        }
+
+        let time = match self.time {
+            Some(time) => time,
+            None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        let code = process_totp(&secret, time, self.period, self.digits, self.algorithm)?;
+        println!("{}", code);
+        eprintln!("Valid for {}s", self.period - time % self.period);
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{get_reader, get_writer, process_pem_unwrap, process_pem_wrap, CmdExector};
+
+use super::verify_file;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::io::Write;
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum PemSubCommand {
+    #[command(about = "Wrap DER/binary data in PEM armor")]
+    Wrap(PemWrapOpts),
+    #[command(about = "Strip PEM armor and output the DER/binary data")]
+    Unwrap(PemUnwrapOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct PemWrapOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// e.g. "PUBLIC KEY", "PRIVATE KEY", "CERTIFICATE"
+    #[arg(short, long)]
+    pub label: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct PemUnwrapOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// fail unless the block has this label
+    #[arg(short, long)]
+    pub label: Option<String>,
+}
+
+impl CmdExector for PemWrapOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        process_pem_wrap(&mut reader, &mut writer, &self.label)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExector for PemUnwrapOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        let label = process_pem_unwrap(&mut reader, &mut writer, self.label.as_deref())?;
+        writer.flush()?;
+        eprintln!("Unwrapped {}", label);
+        Ok(())
+    }
+}
@@ -1,13 +1,27 @@
 use crate::{
-    get_content, get_reader, process_text_key_generate, process_text_sign, process_text_verify,
-    CmdExector,
+    get_content, get_reader, get_writer, process_text_decrypt, process_text_decrypt_identity,
+    process_text_decrypt_passphrase, process_text_encrypt, process_text_encrypt_passphrase,
+    process_text_encrypt_recipients, process_text_key_export, process_text_key_fingerprint,
+    process_text_key_generate, process_text_sign, process_text_sign_envelope,
+    process_text_sign_minisign, process_text_sign_sshsig, process_text_verify,
+    process_text_verify_allowed_signers, process_text_verify_envelope,
+    process_text_verify_minisign, process_text_verify_sshsig, write_secret, CmdExector, KeyError,
+    SignatureEnvelope, SSHSIG_DEFAULT_NAMESPACE,
 };
 
 use super::{verify_file, verify_path};
+use argon2::Params;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-use clap::Parser;
+use clap::{ArgGroup, Args, Parser};
 use enum_dispatch::enum_dispatch;
-use std::{fmt, path::PathBuf, str::FromStr};
+use serde_json::json;
+use std::{
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::fs;
 
 #[derive(Debug, Parser)]
@@ -17,8 +31,16 @@ pub enum TextSubCommand {
     Sign(TextSignOpts),
     #[command(about = "Verify a signature with a public/session key")]
     Verify(TextVerifyOpts),
-    #[command(about = "Generate a random blake3 key or ed25519 key pair")]
+    #[command(about = "Encrypt a text with a xchacha20 or aes256gcm key")]
+    Encrypt(TextEncryptOpts),
+    #[command(about = "Decrypt a text encrypted with a xchacha20 or aes256gcm key")]
+    Decrypt(TextDecryptOpts),
+    #[command(
+        about = "Generate a random blake3, xchacha20 or aes256gcm key or an ed25519 key pair"
+    )]
     Generate(KeyGenerateOpts),
+    #[command(about = "Convert an ed25519 key to raw, PKCS#8 PEM, OpenSSH or JWK")]
+    Export(KeyExportOpts),
 }
 
 #[derive(Debug, Parser)]
@@ -29,24 +51,140 @@ pub struct TextSignOpts {
     pub key: String,
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
+    /// write a json signature file with the key id and a timestamp, or the
+    /// .minisig file with --format minisign
+    #[arg(long)]
+    pub out: Option<String>,
+    /// unsigned comment stored in the signature file, the trusted comment
+    /// for minisign
+    #[arg(long)]
+    pub comment: Option<String>,
+    /// what the sshsig signature is for, e.g. "git" for commits, "file" by
+    /// default
+    #[arg(long)]
+    pub namespace: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct TextVerifyOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
+    #[arg(short, long, value_parser = verify_file, required_unless_present = "allowed_signers")]
+    pub key: Option<String>,
+    #[arg(long, required_unless_present = "sig_file")]
+    pub sig: Option<String>,
+    /// json signature file written by `sign --out`, or a minisign, signify or
+    /// sshsig signature file, its algorithm is used
+    #[arg(long, value_parser = verify_file, conflicts_with_all = ["sig", "format"])]
+    pub sig_file: Option<String>,
+    /// ssh allowed signers file to find the key of a sshsig signature in
+    #[arg(long, value_parser = verify_file, conflicts_with = "key", requires = "sig_file")]
+    pub allowed_signers: Option<String>,
+    /// principal the signer must be allowed as, all are printed if not given
+    #[arg(long, requires = "allowed_signers")]
+    pub principal: Option<String>,
+    /// namespace the sshsig signature must be made for, "file" by default
     #[arg(long)]
-    pub sig: String,
+    pub namespace: Option<String>,
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
+    /// print nothing on success, failures still exit with an error
+    #[arg(short, long, conflicts_with = "json")]
+    pub quiet: bool,
+    /// print the algorithm, key fingerprint and result as json
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("secret").required(true).args(SECRET_ARGS).arg("recipient")))]
+pub struct TextEncryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: Option<String>,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+    /// encrypt to this ed25519 public key, can be repeated
+    #[arg(short, long, value_parser = verify_file, conflicts_with = "format")]
+    pub recipient: Vec<String>,
+    #[arg(long, default_value = "xchacha20", value_parser = parse_text_sign_format)]
+    pub format: TextSignFormat,
+    /// argon2id memory cost in KiB, at most 1 GiB so any machine can decrypt
+    #[arg(long, default_value_t = Params::DEFAULT_M_COST)]
+    pub m_cost: u32,
+    /// argon2id number of iterations
+    #[arg(long, default_value_t = Params::DEFAULT_T_COST)]
+    pub t_cost: u32,
+    /// argon2id degree of parallelism
+    #[arg(long, default_value_t = Params::DEFAULT_P_COST)]
+    pub p_cost: u32,
+    /// write raw bytes instead of base64
+    #[arg(long)]
+    pub binary: bool,
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("secret").required(true).args(SECRET_ARGS).arg("identity")))]
+pub struct TextDecryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: Option<String>,
+    #[command(flatten)]
+    pub passphrase: PassphraseOpts,
+    /// ed25519 signing key of one of the recipients
+    #[arg(long, value_parser = verify_file)]
+    pub identity: Option<String>,
+    /// only used with --key, envelopes record the cipher
+    #[arg(long, default_value = "xchacha20", value_parser = parse_text_sign_format)]
+    pub format: TextSignFormat,
+    /// read raw bytes instead of base64
+    #[arg(long)]
+    pub binary: bool,
+}
+
+const SECRET_ARGS: [&str; 4] = ["key", "passphrase", "passphrase_env", "passphrase_file"];
+
+#[derive(Debug, Args)]
+pub struct PassphraseOpts {
+    /// derive the key from a passphrase, prompted without echo
+    #[arg(long)]
+    pub passphrase: bool,
+    /// read the passphrase from this environment variable
+    #[arg(long, value_name = "VAR")]
+    pub passphrase_env: Option<String>,
+    /// read the passphrase from the first line of this file
+    #[arg(long, value_name = "FILE", value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct KeyGenerateOpts {
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
+    /// file format of ed25519 keys, raw by default; minisign keys default to
+    /// minisign, sshsig keys are always openssh and other keys always raw
+    #[arg(long, value_parser = parse_key_format)]
+    pub key_format: Option<KeyFormat>,
+    #[arg(short, long, value_parser = verify_path)]
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyExportOpts {
+    /// ed25519 key in any supported format
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    /// the key is a public key
+    #[arg(long)]
+    pub public: bool,
+    #[arg(long, value_parser = parse_key_format)]
+    pub key_format: KeyFormat,
     #[arg(short, long, value_parser = verify_path)]
     pub output_path: PathBuf,
 }
@@ -55,6 +193,12 @@ pub struct KeyGenerateOpts {
 pub enum TextSignFormat {
     Blake3,
     Ed25519,
+    Ed25519Ph,
+    XChaCha20,
+    Aes256Gcm,
+    Minisign,
+    Signify,
+    SshSig,
 }
 
 fn parse_text_sign_format(format: &str) -> Result<TextSignFormat, anyhow::Error> {
@@ -68,6 +212,12 @@ impl FromStr for TextSignFormat {
         match s {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::Ed25519),
+            "ed25519ph" => Ok(TextSignFormat::Ed25519Ph),
+            "xchacha20" => Ok(TextSignFormat::XChaCha20),
+            "aes256gcm" => Ok(TextSignFormat::Aes256Gcm),
+            "minisign" => Ok(TextSignFormat::Minisign),
+            "signify" => Ok(TextSignFormat::Signify),
+            "sshsig" => Ok(TextSignFormat::SshSig),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
@@ -78,6 +228,12 @@ impl From<TextSignFormat> for &'static str {
         match format {
             TextSignFormat::Blake3 => "blake3",
             TextSignFormat::Ed25519 => "ed25519",
+            TextSignFormat::Ed25519Ph => "ed25519ph",
+            TextSignFormat::XChaCha20 => "xchacha20",
+            TextSignFormat::Aes256Gcm => "aes256gcm",
+            TextSignFormat::Minisign => "minisign",
+            TextSignFormat::Signify => "signify",
+            TextSignFormat::SshSig => "sshsig",
         }
     }
 }
@@ -88,10 +244,99 @@ impl fmt::Display for TextSignFormat {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum KeyFormat {
+    Raw,
+    Pem,
+    OpenSsh,
+    Jwk,
+    Minisign,
+}
+
+fn parse_key_format(format: &str) -> Result<KeyFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl FromStr for KeyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(KeyFormat::Raw),
+            "pem" => Ok(KeyFormat::Pem),
+            "openssh" => Ok(KeyFormat::OpenSsh),
+            "jwk" => Ok(KeyFormat::Jwk),
+            "minisign" => Ok(KeyFormat::Minisign),
+            _ => Err(anyhow::anyhow!("Invalid key format")),
+        }
+    }
+}
+
+impl From<KeyFormat> for &'static str {
+    fn from(format: KeyFormat) -> Self {
+        match format {
+            KeyFormat::Raw => "raw",
+            KeyFormat::Pem => "pem",
+            KeyFormat::OpenSsh => "openssh",
+            KeyFormat::Jwk => "jwk",
+            KeyFormat::Minisign => "minisign",
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
 impl CmdExector for TextSignOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let mut reader = get_reader(&self.input)?;
         let key = get_content(&self.key)?;
+        if let TextSignFormat::SshSig = self.format {
+            let namespace = self
+                .namespace
+                .as_deref()
+                .unwrap_or(SSHSIG_DEFAULT_NAMESPACE);
+            let sig = process_text_sign_sshsig(&mut reader, &key, namespace)?;
+            let mut writer = get_writer(self.out.as_deref().unwrap_or("-"))?;
+            writer.write_all(sig.as_bytes())?;
+            writer.flush()?;
+            return Ok(());
+        }
+        if self.namespace.is_some() {
+            anyhow::bail!("--namespace only applies to sshsig signatures");
+        }
+        if let TextSignFormat::Minisign = self.format {
+            let trusted_comment = match self.comment {
+                Some(comment) => comment,
+                None => minisign_trusted_comment(&self.input)?,
+            };
+            let minisig = process_text_sign_minisign(&mut reader, &key, &trusted_comment)?;
+            let mut writer = get_writer(self.out.as_deref().unwrap_or("-"))?;
+            writer.write_all(minisig.as_bytes())?;
+            writer.flush()?;
+            return Ok(());
+        }
+        if self.comment.is_some() && self.out.is_none() {
+            anyhow::bail!("--comment is stored in the signature file, use --out");
+        }
+        if let Some(out) = &self.out {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let envelope = process_text_sign_envelope(
+                &mut reader,
+                &key,
+                self.format,
+                timestamp,
+                self.comment,
+            )?;
+            let mut writer = get_writer(out)?;
+            serde_json::to_writer_pretty(&mut writer, &envelope)?;
+            writeln!(writer)?;
+            writer.flush()?;
+            return Ok(());
+        }
         let sig = process_text_sign(&mut reader, &key, self.format)?;
         // base64 output
         let encoded = URL_SAFE_NO_PAD.encode(sig);
@@ -103,23 +348,250 @@ impl CmdExector for TextSignOpts {
 impl CmdExector for TextVerifyOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let mut reader = get_reader(&self.input)?;
-        let key = get_content(&self.key)?;
-        let decoded = URL_SAFE_NO_PAD.decode(&self.sig)?;
-        let verified = process_text_verify(&mut reader, &key, &decoded, self.format)?;
-        if verified {
+        // only missing with --allowed-signers, which has the keys instead
+        let key = self.key.as_deref().map(get_content).transpose()?;
+        let key = key.unwrap_or_default();
+        let mut trusted_comment = None;
+        let mut fingerprint = None;
+        let mut principals = None;
+        let mut signed_namespace = None;
+        let namespace = self
+            .namespace
+            .as_deref()
+            .unwrap_or(SSHSIG_DEFAULT_NAMESPACE);
+        let (format, verified) = match (&self.sig_file, &self.sig) {
+            (Some(sig_file), _) => {
+                let content = String::from_utf8(get_content(sig_file)?)?;
+                let is_sshsig = content
+                    .trim_start()
+                    .starts_with("-----BEGIN SSH SIGNATURE-----");
+                if self.namespace.is_some() && !is_sshsig {
+                    anyhow::bail!("--namespace only applies to sshsig signatures");
+                }
+                if let Some(allowed_signers) = &self.allowed_signers {
+                    if !is_sshsig {
+                        anyhow::bail!("--allowed-signers only works with sshsig signatures");
+                    }
+                    let allowed_signers = String::from_utf8(get_content(allowed_signers)?)?;
+                    let ret = process_text_verify_allowed_signers(
+                        &mut reader,
+                        &allowed_signers,
+                        &content,
+                        namespace,
+                        self.principal.as_deref(),
+                        SystemTime::now(),
+                    )?;
+                    fingerprint = Some(ret.fingerprint);
+                    signed_namespace = Some(ret.namespace);
+                    principals = Some(ret.principals);
+                    (TextSignFormat::SshSig, ret.verified)
+                } else if is_sshsig {
+                    let ret = process_text_verify_sshsig(&mut reader, &key, &content, namespace)?;
+                    fingerprint = Some(ret.fingerprint);
+                    signed_namespace = Some(ret.namespace);
+                    (TextSignFormat::SshSig, ret.verified)
+                } else if content.starts_with("untrusted comment:") {
+                    let ret = process_text_verify_minisign(&mut reader, &key, &content)?;
+                    fingerprint = Some(ret.key_id);
+                    trusted_comment = ret.trusted_comment;
+                    (ret.format, ret.verified)
+                } else {
+                    let envelope: SignatureEnvelope = serde_json::from_str(&content)?;
+                    let verified = process_text_verify_envelope(&mut reader, &key, &envelope)?;
+                    fingerprint = Some(envelope.key_id);
+                    (envelope.algorithm.parse()?, verified)
+                }
+            }
+            (None, Some(_)) if matches!(self.format, TextSignFormat::SshSig) => {
+                anyhow::bail!("sshsig signatures are files, use --sig-file");
+            }
+            (None, Some(_)) if self.namespace.is_some() => {
+                anyhow::bail!("--namespace only applies to sshsig signatures");
+            }
+            (None, Some(sig)) => {
+                let decoded = URL_SAFE_NO_PAD
+                    .decode(sig)
+                    .map_err(|e| KeyError::encoding("signature", e))?;
+                let verified = process_text_verify(&mut reader, &key, &decoded, self.format)?;
+                (self.format, verified)
+            }
+            (None, None) => unreachable!("clap requires --sig or --sig-file"),
+        };
+        if self.json {
+            let fingerprint = match fingerprint {
+                Some(fingerprint) => fingerprint,
+                None => process_text_key_fingerprint(&key, format)?,
+            };
+            let mut result = json!({
+                "algorithm": format.to_string(),
+                "fingerprint": fingerprint,
+                "verified": verified,
+            });
+            if let Some(comment) = trusted_comment {
+                result["trusted_comment"] = json!(comment);
+            }
+            if let Some(namespace) = signed_namespace {
+                result["namespace"] = json!(namespace);
+            }
+            if let Some(principals) = principals {
+                result["principals"] = json!(principals);
+            }
+            println!("{}", result);
+        } else if verified && !self.quiet {
             println!("✓ Signature verified");
+            if let Some(comment) = trusted_comment {
+                println!("Trusted comment: {}", comment);
+            }
+            if let Some(principals) = principals {
+                println!("Signed by {}", principals.join(", "));
+            }
+        }
+        if !verified {
+            // fails like any other error, scripts only need to check for success
+            anyhow::bail!("Signature not verified");
+        }
+        Ok(())
+    }
+}
+
+// public keys like ed25519.pk or id_ed25519.pub can be shared, every other
+// key file is only readable by its owner
+async fn write_key(path: &Path, key: &[u8]) -> anyhow::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if name.ends_with(".pk") || name.split('.').any(|part| part == "pub") {
+        fs::write(path, key).await?;
+    } else {
+        write_secret(path, key)?;
+    }
+    Ok(())
+}
+
+// same as minisign's default trusted comment
+fn minisign_trusted_comment(input: &str) -> anyhow::Result<String> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if input == "-" {
+        return Ok(format!("timestamp:{}", timestamp));
+    }
+    let file = Path::new(input).file_name().unwrap_or_default();
+    Ok(format!(
+        "timestamp:{}\tfile:{}\thashed",
+        timestamp,
+        file.to_string_lossy()
+    ))
+}
+
+impl PassphraseOpts {
+    // None if a key file is used instead
+    fn read(&self, confirm: bool) -> anyhow::Result<Option<String>> {
+        let passphrase = if let Some(var) = &self.passphrase_env {
+            std::env::var(var).map_err(|_| anyhow::anyhow!("{} is not set", var))?
+        } else if let Some(file) = &self.passphrase_file {
+            let content = String::from_utf8(get_content(file)?)?;
+            content.lines().next().unwrap_or_default().to_string()
+        } else if self.passphrase {
+            let passphrase = rpassword::prompt_password("Passphrase: ")?;
+            if confirm && passphrase != rpassword::prompt_password("Confirm passphrase: ")? {
+                anyhow::bail!("Passphrases do not match");
+            }
+            passphrase
         } else {
-            println!("⚠ Signature not verified");
+            return Ok(None);
+        };
+        if passphrase.is_empty() {
+            anyhow::bail!("Passphrase is empty");
+        }
+        Ok(Some(passphrase))
+    }
+}
+
+impl CmdExector for TextEncryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        if !self.recipient.is_empty() {
+            let recipients = self
+                .recipient
+                .iter()
+                .map(|r| get_content(r))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let encrypted = process_text_encrypt_recipients(&mut reader, &recipients)?;
+            return write_encrypted(&self.output, &encrypted, self.binary);
         }
+        let encrypted = match (self.passphrase.read(true)?, &self.key) {
+            (Some(passphrase), _) => {
+                let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+                    .map_err(|e| anyhow::anyhow!("Invalid argon2 parameters: {}", e))?;
+                process_text_encrypt_passphrase(
+                    &mut reader,
+                    passphrase.as_bytes(),
+                    self.format,
+                    params,
+                )?
+            }
+            (None, Some(key)) => {
+                process_text_encrypt(&mut reader, &get_content(key)?, self.format)?
+            }
+            (None, None) => unreachable!("clap requires a key, passphrase or recipient"),
+        };
+        write_encrypted(&self.output, &encrypted, self.binary)
+    }
+}
+
+fn write_encrypted(output: &str, encrypted: &[u8], binary: bool) -> anyhow::Result<()> {
+    let mut writer = get_writer(output)?;
+    if binary {
+        writer.write_all(encrypted)?;
+    } else {
+        writeln!(writer, "{}", URL_SAFE_NO_PAD.encode(encrypted))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl CmdExector for TextDecryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let input = get_content(&self.input)?;
+        let input = if self.binary {
+            input
+        } else {
+            URL_SAFE_NO_PAD.decode(input.trim_ascii())?
+        };
+        let decrypted = match (&self.identity, self.passphrase.read(false)?, &self.key) {
+            (Some(identity), _, _) => {
+                process_text_decrypt_identity(&mut input.as_slice(), &get_content(identity)?)?
+            }
+            (None, Some(passphrase), _) => {
+                process_text_decrypt_passphrase(&mut input.as_slice(), passphrase.as_bytes())?
+            }
+            (None, None, Some(key)) => {
+                process_text_decrypt(&mut input.as_slice(), &get_content(key)?, self.format)?
+            }
+            (None, None, None) => unreachable!("clap requires a key, passphrase or identity"),
+        };
+        let mut writer = get_writer(&self.output)?;
+        writer.write_all(&decrypted)?;
+        writer.flush()?;
         Ok(())
     }
 }
 
 impl CmdExector for KeyGenerateOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let key = process_text_key_generate(self.format)?;
+        let key = process_text_key_generate(self.format, self.key_format)?;
+        for (k, v) in key {
+            write_key(&self.output_path.join(k), &v).await?;
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for KeyExportOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = get_content(&self.key)?;
+        let key = process_text_key_export(&key, self.public, self.key_format)?;
         for (k, v) in key {
-            fs::write(self.output_path.join(k), v).await?;
+            let path = self.output_path.join(k);
+            write_key(&path, &v).await?;
+            eprintln!("Wrote {}", path.display());
         }
         Ok(())
     }
@@ -0,0 +1,92 @@
+use crate::{get_reader, process_url_decode, process_url_encode, CmdExector};
+
+use super::verify_file;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExector)]
+pub enum UrlSubCommand {
+    #[command(name = "encode", about = "Percent-encode a string")]
+    Encode(UrlEncodeOpts),
+    #[command(name = "decode", about = "Decode a percent-encoded string")]
+    Decode(UrlDecodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct UrlEncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = parse_url_encode_mode, default_value = "component")]
+    pub mode: UrlEncodeMode,
+}
+
+#[derive(Debug, Parser)]
+pub struct UrlDecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = parse_url_encode_mode, default_value = "component")]
+    pub mode: UrlEncodeMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UrlEncodeMode {
+    // a single path segment or query value (RFC 3986)
+    Component,
+    // a full url, reserved characters like / ? & = are kept
+    Url,
+    // application/x-www-form-urlencoded, space is '+'
+    Form,
+}
+
+fn parse_url_encode_mode(mode: &str) -> Result<UrlEncodeMode, anyhow::Error> {
+    mode.parse()
+}
+
+impl FromStr for UrlEncodeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "component" => Ok(UrlEncodeMode::Component),
+            "url" => Ok(UrlEncodeMode::Url),
+            "form" => Ok(UrlEncodeMode::Form),
+            _ => Err(anyhow::anyhow!("Invalid mode")),
+        }
+    }
+}
+
+impl From<UrlEncodeMode> for &'static str {
+    fn from(mode: UrlEncodeMode) -> Self {
+        match mode {
+            UrlEncodeMode::Component => "component",
+            UrlEncodeMode::Url => "url",
+            UrlEncodeMode::Form => "form",
+        }
+    }
+}
+
+impl fmt::Display for UrlEncodeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl CmdExector for UrlEncodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let ret = process_url_encode(&mut reader, self.mode)?;
+        println!("{}", ret);
+        Ok(())
+    }
+}
+
+impl CmdExector for UrlDecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let ret = process_url_decode(&mut reader, self.mode)?;
+        println!("{}", ret);
+        Ok(())
+    }
+}
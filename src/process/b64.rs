@@ -1,34 +1,259 @@
-use crate::Base64Format;
+use crate::{
+    process::codec::{log_likelihood, rank_candidates},
+    Base64Format,
+};
 use anyhow::Result;
 use base64::{
-    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    alphabet::{self, Alphabet},
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    read::DecoderReader,
+    write::EncoderWriter,
     Engine as _,
 };
-use std::io::Read;
-
-pub fn process_encode(reader: &mut dyn Read, format: Base64Format) -> Result<String> {
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
-    let encoded = match format {
-        Base64Format::Standard => STANDARD.encode(&buf),
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.encode(&buf),
-    };
+use std::io::{self, Read, Write};
+
+// MIME (RFC 2045) and PEM (RFC 7468) line lengths
+pub const MIME_LINE_WIDTH: usize = 76;
+pub const PEM_LINE_WIDTH: usize = 64;
+// bytes looked at to guess the mime type of a data uri
+const SNIFF_LEN: usize = 512;
+// (offset, magic bytes, mime type)
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (0, b"\x00\x00\x01\x00", "image/x-icon"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"ID3", "audio/mpeg"),
+    (4, b"ftyp", "video/mp4"),
+];
+
+/// Stream `reader` into `writer` as base64, optionally wrapping lines at `wrap` columns.
+/// No trailing newline is written.
+pub fn process_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+    wrap: Option<usize>,
+) -> Result<()> {
+    if let Base64Format::Auto = format {
+        anyhow::bail!("Auto format can only be used to decode");
+    }
+    let mut wrapper = LineWrapWriter::new(writer, wrap);
+    let engine = engine(format, false);
+    let mut encoder = EncoderWriter::new(&mut wrapper, &engine);
+    io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Stream `reader` into `writer` as a `data:<mime>;base64,...` uri. Without
+/// an explicit `mime` the type is sniffed from the first bytes.
+pub fn process_encode_data_uri(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    mime: Option<&str>,
+) -> Result<()> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    (&mut *reader)
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    let mime = mime.unwrap_or_else(|| sniff_mime(&head));
+    write!(writer, "data:{};base64,", mime)?;
+    let mut reader = head.as_slice().chain(reader);
+    process_encode(&mut reader, writer, Base64Format::Standard, None)
+}
+
+/// Stream base64 from `reader` into `writer` as raw bytes. Whitespace (line
+/// breaks from wrapped input, accidental trailing newlines) is ignored. A
+/// `lenient` decoder accepts input with or without padding.
+///
+/// `Base64Format::Auto` buffers the input and decodes with the most likely
+/// format from `process_base64_detect`.
+pub fn process_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+    lenient: bool,
+) -> Result<()> {
+    if let Base64Format::Auto = format {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        let (format, _) = process_base64_detect(&buf)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Input is not valid base64 in any known format"))?;
+        return process_decode(&mut &buf[..], writer, format, lenient);
+    }
 
-    Ok(encoded)
+    let filter = WhitespaceFilter::new(reader);
+    let engine = engine(format, lenient);
+    let mut decoder = DecoderReader::new(filter, &engine);
+    io::copy(&mut decoder, writer)?;
+    Ok(())
 }
 
-pub fn process_decode(reader: &mut dyn Read, format: Base64Format) -> Result<String> {
-    let mut buf = String::new();
-    reader.read_to_string(&mut buf)?;
-    // avoid accidental newlines
-    let buf = buf.trim();
+fn sniff_mime(head: &[u8]) -> &'static str {
+    if let Some((_, _, mime)) = MAGIC
+        .iter()
+        .find(|(offset, magic, _)| head.get(*offset..).is_some_and(|h| h.starts_with(magic)))
+    {
+        return mime;
+    }
+    match std::str::from_utf8(head) {
+        // a multi-byte character cut at the end of `head` is still text
+        Err(e) if e.error_len().is_some() => "application/octet-stream",
+        _ if head.windows(4).any(|w| w == b"<svg") => "image/svg+xml",
+        _ => "text/plain;charset=utf-8",
+    }
+}
+
+/// Guess the alphabet and padding of base64 `input`. Returns the formats that
+/// decode it, best first, each with a confidence between 0 and 1.
+///
+/// Most input fits every alphabet, so the bcrypt and crypt alphabets are
+/// only considered with a '.' in the input and the IMAP one with a ','.
+pub fn process_base64_detect(input: &[u8]) -> Vec<(Base64Format, f64)> {
+    let input: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let symbols = input.iter().filter(|c| **c != b'=').count();
+    // padding is visible in the input, only the alphabet is a guess
+    let padded = input.ends_with(b"=");
+    let standard = if padded || input.len().is_multiple_of(4) {
+        Base64Format::Standard
+    } else {
+        Base64Format::StandardNoPad
+    };
+    let url_safe = if padded {
+        Base64Format::UrlSafePad
+    } else {
+        Base64Format::UrlSafe
+    };
+
+    let mut formats = vec![standard, url_safe];
+    if input.contains(&b'.') {
+        formats.extend([Base64Format::Bcrypt, Base64Format::Crypt]);
+    }
+    if input.contains(&b',') {
+        formats.push(Base64Format::Imap);
+    }
+
+    let candidates = formats
+        .into_iter()
+        .filter(|format| engine(*format, false).decode(&input).is_ok())
+        .map(|format| (format, log_likelihood(symbols, 64)))
+        .collect();
+    rank_candidates(candidates)
+}
 
-    let decoded = match format {
-        Base64Format::Standard => STANDARD.decode(buf)?,
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.decode(buf)?,
+fn engine(format: Base64Format, lenient: bool) -> GeneralPurpose {
+    let (alphabet, padding): (&Alphabet, bool) = match format {
+        Base64Format::Standard => (&alphabet::STANDARD, true),
+        Base64Format::StandardNoPad => (&alphabet::STANDARD, false),
+        Base64Format::UrlSafe => (&alphabet::URL_SAFE, false),
+        Base64Format::UrlSafePad => (&alphabet::URL_SAFE, true),
+        Base64Format::Bcrypt => (&alphabet::BCRYPT, false),
+        Base64Format::Crypt => (&alphabet::CRYPT, false),
+        Base64Format::Imap => (&alphabet::IMAP_MUTF7, false),
+        Base64Format::Auto => unreachable!("auto is resolved before building an engine"),
+    };
+    let mode = match (lenient, padding) {
+        (true, _) => DecodePaddingMode::Indifferent,
+        (false, true) => DecodePaddingMode::RequireCanonical,
+        (false, false) => DecodePaddingMode::RequireNone,
     };
-    // TODO: decoded data might not be string (but for this example, we assume it is)
-    Ok(String::from_utf8(decoded)?)
+    let config = GeneralPurposeConfig::new()
+        .with_encode_padding(padding)
+        .with_decode_padding_mode(mode);
+    GeneralPurpose::new(alphabet, config)
+}
+
+/// Inserts a newline every `width` bytes. The newline is only written once
+/// more data arrives, so the output never ends with one.
+struct LineWrapWriter<W> {
+    inner: W,
+    width: Option<usize>,
+    column: usize,
+}
+
+impl<W: Write> LineWrapWriter<W> {
+    fn new(inner: W, width: Option<usize>) -> Self {
+        Self {
+            inner,
+            width,
+            column: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for LineWrapWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let width = match self.width {
+            Some(width) if width > 0 => width,
+            _ => return self.inner.write(buf),
+        };
+
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.column == width {
+                self.inner.write_all(b"\n")?;
+                self.column = 0;
+            }
+            let n = rest.len().min(width - self.column);
+            self.inner.write_all(&rest[..n])?;
+            self.column += n;
+            rest = &rest[n..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Drops ASCII whitespace from the underlying reader.
+struct WhitespaceFilter<R> {
+    inner: R,
+}
+
+impl<R: Read> WhitespaceFilter<R> {
+    fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Read> Read for WhitespaceFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut len = 0;
+            for i in 0..n {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[len] = buf[i];
+                    len += 1;
+                }
+            }
+            // a chunk of pure whitespace is not EOF, keep reading
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -41,7 +266,9 @@ mod tests {
         let input = "Cargo.toml";
         let mut reader = get_reader(input)?;
         let format = Base64Format::Standard;
-        assert!(process_encode(&mut reader, format).is_ok());
+        let mut buf = Vec::new();
+        assert!(process_encode(&mut reader, &mut buf, format, None).is_ok());
+        assert!(!buf.contains(&b'\n'));
         Ok(())
     }
 
@@ -50,8 +277,226 @@ mod tests {
         let input = "fixtures/b64.txt";
         let mut reader = get_reader(input)?;
         let format = Base64Format::UrlSafe;
-        process_decode(&mut reader, format)?;
+        let mut decoded = Vec::new();
+        process_decode(&mut reader, &mut decoded, format, false)?;
+        assert!(decoded.starts_with(b"[package]"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_decode_binary() -> Result<()> {
+        let input = include_bytes!("../../fixtures/ed25519.pk");
+        let mut encoded = Vec::new();
+        process_encode(&mut &input[..], &mut encoded, Base64Format::Standard, None)?;
+        let mut decoded = Vec::new();
+        process_decode(
+            &mut &encoded[..],
+            &mut decoded,
+            Base64Format::Standard,
+            false,
+        )?;
+        assert_eq!(decoded, input);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_encode_wrap() -> Result<()> {
+        let input = [0u8; 120];
+        let mut encoded = Vec::new();
+        process_encode(
+            &mut &input[..],
+            &mut encoded,
+            Base64Format::Standard,
+            Some(PEM_LINE_WIDTH),
+        )?;
+        let encoded = String::from_utf8(encoded)?;
+        let lines: Vec<_> = encoded.lines().collect();
+        // 120 bytes -> 160 chars -> 64 + 64 + 32
+        assert_eq!(lines.len(), 3);
+        assert!(lines[..2].iter().all(|l| l.len() == PEM_LINE_WIDTH));
+        assert_eq!(lines[2].len(), 32);
+        assert!(!encoded.ends_with('\n'));
+
+        let mut decoded = Vec::new();
+        process_decode(
+            &mut encoded.as_bytes(),
+            &mut decoded,
+            Base64Format::Standard,
+            false,
+        )?;
+        assert_eq!(decoded, input);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_decode_whitespace() -> Result<()> {
+        let mut decoded = Vec::new();
+        process_decode(
+            &mut " aGVs\r\nbG8g\n\n  d29y\tbGQ=\n".as_bytes(),
+            &mut decoded,
+            Base64Format::Standard,
+            false,
+        )?;
+        assert_eq!(decoded, b"hello world");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_formats() -> Result<()> {
+        let input = b"\xfb\xff\xbf hello";
+        let cases = [
+            (Base64Format::Standard, "+/+/IGhlbGxv"),
+            (Base64Format::StandardNoPad, "+/+/IGhlbGxv"),
+            (Base64Format::UrlSafe, "-_-_IGhlbGxv"),
+            (Base64Format::UrlSafePad, "-_-_IGhlbGxv"),
+            (Base64Format::Bcrypt, "8989GEfjZEvt"),
+            (Base64Format::Crypt, "yzyz64VZP4lj"),
+            (Base64Format::Imap, "+,+,IGhlbGxv"),
+        ];
+        for (format, expected) in cases {
+            let mut encoded = Vec::new();
+            process_encode(&mut &input[..], &mut encoded, format, None)?;
+            assert_eq!(String::from_utf8(encoded.clone())?, expected, "{}", format);
+
+            let mut decoded = Vec::new();
+            process_decode(&mut &encoded[..], &mut decoded, format, false)?;
+            assert_eq!(decoded, input, "{}", format);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_decode_padding() -> Result<()> {
+        let mut buf = Vec::new();
+        process_encode(&mut &b"hi"[..], &mut buf, Base64Format::UrlSafePad, None)?;
+        assert_eq!(buf, b"aGk=");
+        buf.clear();
+        process_encode(&mut &b"hi"[..], &mut buf, Base64Format::StandardNoPad, None)?;
+        assert_eq!(buf, b"aGk");
+
+        // strict decoders reject the other padding, lenient ones accept both
+        let mut decoded = Vec::new();
+        assert!(process_decode(
+            &mut "aGk".as_bytes(),
+            &mut decoded,
+            Base64Format::Standard,
+            false
+        )
+        .is_err());
+        assert!(process_decode(
+            &mut "aGk=".as_bytes(),
+            &mut decoded,
+            Base64Format::UrlSafe,
+            false
+        )
+        .is_err());
+        for input in ["aGk", "aGk="] {
+            let mut decoded = Vec::new();
+            process_decode(
+                &mut input.as_bytes(),
+                &mut decoded,
+                Base64Format::Standard,
+                true,
+            )?;
+            assert_eq!(decoded, b"hi");
+            let mut decoded = Vec::new();
+            process_decode(
+                &mut input.as_bytes(),
+                &mut decoded,
+                Base64Format::UrlSafe,
+                true,
+            )?;
+            assert_eq!(decoded, b"hi");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_base64_detect() -> Result<()> {
+        let ret = process_base64_detect(b"aGVsbG8+/w==");
+        assert_eq!(ret.len(), 1);
+        assert!(matches!(ret[0], (Base64Format::Standard, c) if c == 1.0));
+
+        let ret = process_base64_detect(b"aGVsbG8-_w");
+        assert_eq!(ret.len(), 1);
+        assert!(matches!(ret[0].0, Base64Format::UrlSafe));
+
+        // no alphabet specific characters: both fit, standard wins the tie
+        let ret = process_base64_detect(b"aGVsbG8");
+        assert_eq!(ret.len(), 2);
+        assert!(matches!(ret[0].0, Base64Format::StandardNoPad));
+        assert_eq!(ret[0].1, 0.5);
+
+        // bcrypt and crypt share their characters, bcrypt wins the tie
+        let ret = process_base64_detect(b".EfjZEvt");
+        assert_eq!(ret.len(), 2);
+        assert!(matches!(ret[0].0, Base64Format::Bcrypt));
+        assert!(matches!(ret[1].0, Base64Format::Crypt));
+
+        let ret = process_base64_detect(b"+,+,IGhlbGxv");
+        assert_eq!(ret.len(), 1);
+        assert!(matches!(ret[0].0, Base64Format::Imap));
+
+        let mut decoded = Vec::new();
+        process_decode(
+            &mut "aGVsbG8-_w".as_bytes(),
+            &mut decoded,
+            Base64Format::Auto,
+            false,
+        )?;
+        assert_eq!(decoded, b"hello>\xff");
+        assert!(process_decode(
+            &mut "a+b-".as_bytes(),
+            &mut decoded,
+            Base64Format::Auto,
+            false
+        )
+        .is_err());
+        assert!(process_encode(
+            &mut "hello".as_bytes(),
+            &mut decoded,
+            Base64Format::Auto,
+            None
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_encode_data_uri() -> Result<()> {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+        let mut buf = Vec::new();
+        process_encode_data_uri(&mut &png[..], &mut buf, None)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg=="
+        );
+
+        let mut buf = Vec::new();
+        process_encode_data_uri(&mut "hello".as_bytes(), &mut buf, None)?;
+        assert_eq!(buf, b"data:text/plain;charset=utf-8;base64,aGVsbG8=");
+
+        let mut buf = Vec::new();
+        process_encode_data_uri(&mut "{}".as_bytes(), &mut buf, Some("application/json"))?;
+        assert_eq!(buf, b"data:application/json;base64,e30=");
 
+        // longer than the sniffed head, nothing may get lost
+        let input = vec![0xffu8; SNIFF_LEN * 3];
+        let mut buf = Vec::new();
+        process_encode_data_uri(&mut &input[..], &mut buf, None)?;
+        let encoded = String::from_utf8(buf)?;
+        let data = encoded
+            .strip_prefix("data:application/octet-stream;base64,")
+            .expect("binary data");
+        let mut decoded = Vec::new();
+        process_decode(
+            &mut data.as_bytes(),
+            &mut decoded,
+            Base64Format::Standard,
+            false,
+        )?;
+        assert_eq!(decoded, input);
         Ok(())
     }
 }
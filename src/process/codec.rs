@@ -0,0 +1,318 @@
+use crate::{process_decode, process_encode, Base64Format, Codec};
+use anyhow::Result;
+use data_encoding::{Encoding, Specification, BASE32, BASE32HEX, HEXLOWER, HEXLOWER_PERMISSIVE};
+use std::io::{self, Read, Write};
+
+const ASCII85_ALPHABET: &[u8; 85] =
+    b"!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstu";
+const Z85_ALPHABET: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+const ZBASE32_SYMBOLS: &str = "ybndrfg8ejkmcpqxot1uwisza345h769";
+
+/// Encode everything from `reader` with `codec` into `writer`, without a trailing newline.
+pub fn process_codec_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    codec: Codec,
+) -> Result<()> {
+    let format = match codec {
+        Codec::Base64 => Some(Base64Format::Standard),
+        Codec::UrlSafe => Some(Base64Format::UrlSafe),
+        _ => None,
+    };
+    if let Some(format) = format {
+        return process_encode(reader, writer, format, None);
+    }
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let encoded = match codec {
+        Codec::Hex => HEXLOWER.encode(&buf),
+        Codec::Base32 => BASE32.encode(&buf),
+        Codec::Base32Hex => BASE32HEX.encode(&buf),
+        Codec::ZBase32 => zbase32()?.encode(&buf),
+        Codec::Base58 => bs58::encode(&buf).into_string(),
+        Codec::Base58Check => bs58::encode(&buf).with_check().into_string(),
+        Codec::Ascii85 => ascii85_encode(&buf),
+        Codec::Z85 => z85_encode(&buf)?,
+        Codec::Base64 | Codec::UrlSafe => unreachable!("base64 codecs are streamed"),
+    };
+    writer.write_all(encoded.as_bytes())?;
+    Ok(())
+}
+
+/// Decode `codec` text from `reader` and write the raw bytes into `writer`.
+/// Whitespace in the input is ignored.
+pub fn process_codec_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    codec: Codec,
+) -> Result<()> {
+    let format = match codec {
+        Codec::Base64 => Some(Base64Format::Standard),
+        Codec::UrlSafe => Some(Base64Format::UrlSafe),
+        _ => None,
+    };
+    if let Some(format) = format {
+        return process_decode(reader, writer, format, true);
+    }
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    buf.retain(|c| !c.is_ascii_whitespace());
+    let decoded = match codec {
+        Codec::Hex => HEXLOWER_PERMISSIVE.decode(&buf)?,
+        Codec::Base32 => {
+            buf.make_ascii_uppercase();
+            BASE32.decode(&buf)?
+        }
+        Codec::Base32Hex => {
+            buf.make_ascii_uppercase();
+            BASE32HEX.decode(&buf)?
+        }
+        Codec::ZBase32 => zbase32()?.decode(&buf)?,
+        Codec::Base58 => bs58::decode(&buf).into_vec()?,
+        Codec::Base58Check => bs58::decode(&buf).with_check(None).into_vec()?,
+        Codec::Ascii85 => ascii85_decode(&buf)?,
+        Codec::Z85 => z85_decode(&buf)?,
+        Codec::Base64 | Codec::UrlSafe => unreachable!("base64 codecs are streamed"),
+    };
+    writer.write_all(&decoded)?;
+    Ok(())
+}
+
+/// Guess which of hex, base32 and base64 (standard or url safe) `input` is
+/// encoded with. Returns the candidates that decode, best first, each with
+/// a confidence between 0 and 1.
+pub fn process_codec_detect(input: &[u8]) -> Vec<(Codec, f64)> {
+    let input: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let symbols = input.iter().filter(|c| **c != b'=').count();
+
+    let candidates = [
+        (Codec::Hex, 16),
+        (Codec::Base32, 32),
+        (Codec::Base64, 64),
+        (Codec::UrlSafe, 64),
+    ]
+    .into_iter()
+    .filter(|(codec, _)| process_codec_decode(&mut &input[..], &mut io::sink(), *codec).is_ok())
+    .map(|(codec, alphabet)| (codec, log_likelihood(symbols, alphabet)))
+    .collect();
+    rank_candidates(candidates)
+}
+
+/// log2 of the chance that `symbols` random characters from an alphabet of
+/// `alphabet` characters happen to form the input.
+pub(crate) fn log_likelihood(symbols: usize, alphabet: usize) -> f64 {
+    -(symbols as f64) * (alphabet as f64).log2()
+}
+
+/// Turn log likelihoods into confidences (a softmax with equal priors), so
+/// the smallest alphabet that still fits the input wins. Ties keep their order.
+pub(crate) fn rank_candidates<T>(candidates: Vec<(T, f64)>) -> Vec<(T, f64)> {
+    let max = candidates
+        .iter()
+        .map(|(_, l)| *l)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let total: f64 = candidates.iter().map(|(_, l)| (l - max).exp2()).sum();
+    let mut ret: Vec<(T, f64)> = candidates
+        .into_iter()
+        .map(|(c, l)| (c, (l - max).exp2() / total))
+        .collect();
+    ret.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ret
+}
+
+fn zbase32() -> Result<Encoding> {
+    let mut spec = Specification::new();
+    spec.symbols.push_str(ZBASE32_SYMBOLS);
+    Ok(spec.encoding()?)
+}
+
+// every 4 bytes become 5 base85 digits, most significant first
+fn base85_chunk(chunk: &[u8], alphabet: &[u8; 85]) -> [u8; 5] {
+    let mut word = [0u8; 4];
+    word[..chunk.len()].copy_from_slice(chunk);
+    let mut value = u32::from_be_bytes(word);
+    let mut out = [0u8; 5];
+    for c in out.iter_mut().rev() {
+        *c = alphabet[(value % 85) as usize];
+        value /= 85;
+    }
+    out
+}
+
+fn base85_value(chars: &[u8], digit: impl Fn(u8) -> Option<u32>) -> Result<[u8; 4]> {
+    let mut value: u64 = 0;
+    for c in chars {
+        let d = digit(*c)
+            .ok_or_else(|| anyhow::anyhow!("Invalid base85 character {:?}", *c as char))?;
+        value = value * 85 + d as u64;
+    }
+    let value: u32 = value
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Base85 group out of range"))?;
+    Ok(value.to_be_bytes())
+}
+
+/// Adobe ascii85, with the `z` shorthand for zero groups and no `<~ ~>` delimiters.
+fn ascii85_encode(data: &[u8]) -> String {
+    let mut out = Vec::with_capacity(data.len() / 4 * 5 + 5);
+    for chunk in data.chunks(4) {
+        if chunk == [0, 0, 0, 0] {
+            out.push(b'z');
+        } else {
+            // a partial chunk of n bytes needs only n + 1 digits
+            out.extend_from_slice(&base85_chunk(chunk, ASCII85_ALPHABET)[..chunk.len() + 1]);
+        }
+    }
+    String::from_utf8(out).expect("ascii85 output is ascii")
+}
+
+fn ascii85_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let data = data.strip_prefix(b"<~").unwrap_or(data);
+    let data = data.strip_suffix(b"~>").unwrap_or(data);
+    let digit = |c: u8| (b'!'..=b'u').contains(&c).then(|| (c - b'!') as u32);
+
+    let mut out = Vec::with_capacity(data.len() / 5 * 4 + 4);
+    let mut group = Vec::with_capacity(5);
+    for &c in data {
+        if c == b'z' && group.is_empty() {
+            out.extend_from_slice(&[0; 4]);
+            continue;
+        }
+        group.push(c);
+        if group.len() == 5 {
+            out.extend_from_slice(&base85_value(&group, digit)?);
+            group.clear();
+        }
+    }
+    match group.len() {
+        0 => {}
+        1 => anyhow::bail!("Invalid ascii85 length"),
+        n => {
+            // pad with the highest digit, then drop the padding bytes
+            group.resize(5, b'u');
+            out.extend_from_slice(&base85_value(&group, digit)?[..n - 1]);
+        }
+    }
+    Ok(out)
+}
+
+/// ZeroMQ Z85 (RFC 32), the input length must be a multiple of 4.
+fn z85_encode(data: &[u8]) -> Result<String> {
+    if !data.len().is_multiple_of(4) {
+        anyhow::bail!("Z85 input length must be a multiple of 4");
+    }
+    let out: Vec<u8> = data
+        .chunks(4)
+        .flat_map(|chunk| base85_chunk(chunk, Z85_ALPHABET))
+        .collect();
+    Ok(String::from_utf8(out)?)
+}
+
+fn z85_decode(data: &[u8]) -> Result<Vec<u8>> {
+    if !data.len().is_multiple_of(5) {
+        anyhow::bail!("Z85 input length must be a multiple of 5");
+    }
+    let digit = |c: u8| Z85_ALPHABET.iter().position(|a| *a == c).map(|d| d as u32);
+    let mut out = Vec::with_capacity(data.len() / 5 * 4);
+    for group in data.chunks(5) {
+        out.extend_from_slice(&base85_value(group, digit)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(data: &[u8], codec: Codec) -> Result<String> {
+        let mut buf = Vec::new();
+        process_codec_encode(&mut &data[..], &mut buf, codec)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    fn decode(data: &str, codec: Codec) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        process_codec_decode(&mut data.as_bytes(), &mut buf, codec)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_process_codec() -> Result<()> {
+        let cases: [(&[u8], Codec, &str); 10] = [
+            (b"hello", Codec::Hex, "68656c6c6f"),
+            (b"hello", Codec::Base32, "NBSWY3DP"),
+            (b"hello", Codec::Base32Hex, "D1IMOR3F"),
+            (b"hello", Codec::ZBase32, "pb1sa5dx"),
+            (b"hello world", Codec::Base58, "StV1DL6CwTryKyV"),
+            (b"hello world", Codec::Ascii85, "BOu!rD]j7BEbo7"),
+            (b"\0\0\0\0ab", Codec::Ascii85, "z@:B"),
+            (
+                &[0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b],
+                Codec::Z85,
+                "HelloWorld",
+            ),
+            (b"hi", Codec::Base64, "aGk="),
+            (b"hi", Codec::UrlSafe, "aGk"),
+        ];
+        for (data, codec, expected) in cases {
+            assert_eq!(encode(data, codec)?, expected, "{}", codec);
+            assert_eq!(decode(expected, codec)?, data, "{}", codec);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_codec_decode_lenient_input() -> Result<()> {
+        assert_eq!(decode("68 65 6C\n6c 6F\n", Codec::Hex)?, b"hello");
+        assert_eq!(decode("nbswy3dp", Codec::Base32)?, b"hello");
+        assert_eq!(
+            decode("<~BOu!rD]j7BEbo7~>", Codec::Ascii85)?,
+            b"hello world"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_codec_base58check() -> Result<()> {
+        let encoded = encode(b"hello", Codec::Base58Check)?;
+        assert_eq!(decode(&encoded, Codec::Base58Check)?, b"hello");
+
+        // flip the last character, the checksum must catch it
+        let mut broken = encoded.into_bytes();
+        let last = broken.len() - 1;
+        broken[last] = if broken[last] == b'1' { b'2' } else { b'1' };
+        assert!(decode(std::str::from_utf8(&broken)?, Codec::Base58Check).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_codec_detect() {
+        let best = |input: &str| process_codec_detect(input.as_bytes())[0].0.to_string();
+        assert_eq!(best("68656c6c6f20776f726c64"), "hex");
+        assert_eq!(best("NBSWY3DPEB3W64TMMQ======"), "base32");
+        assert_eq!(best("aGVsbG8+/w=="), "base64");
+        assert_eq!(best("aGVsbG8-_w"), "urlsafe");
+
+        let ret = process_codec_detect(b"68656c6c6f20776f726c64");
+        assert!(ret[0].1 > 0.99);
+        let total: f64 = ret.iter().map(|(_, c)| c).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        assert!(process_codec_detect(b"not encoded!").is_empty());
+    }
+
+    #[test]
+    fn test_process_codec_invalid() {
+        assert!(encode(b"abc", Codec::Z85).is_err());
+        assert!(decode("abcd", Codec::Z85).is_err());
+        assert!(decode("zz", Codec::Hex).is_err());
+        assert!(decode("B", Codec::Ascii85).is_err());
+    }
+}
@@ -1,46 +1,308 @@
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use zxcvbn::zxcvbn;
 
 const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
 const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
 const NUMBER: &[u8] = b"123456789";
 const SYMBOL: &[u8] = b"!@#$%^&*_";
 
-pub fn process_genpass(
-    length: u8,
+// zxcvbn is not cheap, so don't loop forever on an unreachable score
+const MAX_ATTEMPTS: usize = 100;
+// changing this changes every derived password, so never do it
+const DERIVE_CONTEXT: &str = "rcli 2024-03-25 genpass derive site password v1";
+
+#[derive(Debug)]
+pub struct GenPass {
+    pub password: String,
+    pub score: u8,
+    // bits, see `process_genpass`
+    pub entropy: f64,
+}
+
+/// Generate a password, regenerating until zxcvbn scores it at least `min_score`.
+///
+/// Every character is drawn uniformly from the union of the enabled sets, and
+/// candidates missing one of the sets are rejected and redrawn. The result is
+/// uniform over all valid passwords, and `entropy` is log2 of their number,
+/// a bit less than `length * log2(charset size)`. Candidates below
+/// `min_score` are rejected as well, so with a `min_score` it is an upper
+/// bound.
+pub fn process_genpass<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    length: usize,
     upper: bool,
     lower: bool,
     number: bool,
     symbol: bool,
+    min_score: u8,
+) -> anyhow::Result<GenPass> {
+    let sets: Vec<&[u8]> = [
+        (upper, UPPER),
+        (lower, LOWER),
+        (number, NUMBER),
+        (symbol, SYMBOL),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, set)| enabled.then_some(set))
+    .collect();
+    if sets.is_empty() {
+        anyhow::bail!("At least one character set must be enabled");
+    }
+    if length < sets.len() {
+        anyhow::bail!(
+            "Length {} is too short for {} character sets",
+            length,
+            sets.len()
+        );
+    }
+    let chars = sets.concat();
+    let entropy = entropy(length, &sets);
+
+    for _ in 0..MAX_ATTEMPTS {
+        let password = generate(rng, length, &chars, &sets)?;
+        let score = zxcvbn(&password, &[])?.score();
+        if score >= min_score {
+            return Ok(GenPass {
+                password,
+                score,
+                entropy,
+            });
+        }
+    }
+
+    anyhow::bail!(
+        "Could not reach score {} in {} attempts, try a longer length or more character sets",
+        min_score,
+        MAX_ATTEMPTS
+    )
+}
+
+/// Generate `length` raw random bytes, e.g. for API secrets or key material.
+pub fn process_genbytes<R: RngCore + CryptoRng>(rng: &mut R, length: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; length];
+    rng.fill_bytes(&mut buf);
+    buf
+}
+
+/// Build a deterministic rng for `site` from a master key, so the same
+/// (master key, site, counter) always yields the same password.
+pub fn process_genpass_derive_rng(master_key: &[u8], site: &str, counter: u32) -> ChaCha20Rng {
+    let site = site.trim().to_lowercase();
+    let mut hasher = blake3::Hasher::new_derive_key(DERIVE_CONTEXT);
+    hasher.update(master_key);
+    hasher.update(&(site.len() as u64).to_le_bytes());
+    hasher.update(site.as_bytes());
+    hasher.update(&counter.to_le_bytes());
+    ChaCha20Rng::from_seed(*hasher.finalize().as_bytes())
+}
+
+// log2 of the number of passwords with a character of every set: n^length
+// times the fraction left after inclusion-exclusion over the missing sets
+fn entropy(length: usize, sets: &[&[u8]]) -> f64 {
+    let n = sets.iter().map(|set| set.len()).sum::<usize>() as f64;
+    let mut fraction = 0.0;
+    for mask in 0..1u32 << sets.len() {
+        let missing: usize = sets
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, set)| set.len())
+            .sum();
+        let sign = if mask.count_ones() % 2 == 0 {
+            1.0
+        } else {
+            -1.0
+        };
+        fraction += sign * (1.0 - missing as f64 / n).powf(length as f64);
+    }
+    length as f64 * n.log2() + fraction.log2()
+}
+
+fn generate<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    length: usize,
+    chars: &[u8],
+    sets: &[&[u8]],
 ) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
+    // `choose` is unbiased, and rejecting (instead of patching) passwords
+    // without every set keeps the distribution uniform
+    loop {
+        let password: Vec<u8> = (0..length)
+            .map(|_| {
+                *chars
+                    .choose(rng)
+                    .expect("chars won't be empty in this context")
+            })
+            .collect();
+        if sets
+            .iter()
+            .all(|set| password.iter().any(|c| set.contains(c)))
+        {
+            return Ok(String::from_utf8(password)?);
+        }
+    }
+}
 
-    if upper {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("UPPER won't be empty"));
+#[derive(Debug)]
+pub struct PasswordStrength {
+    pub score: u8,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+    // (scenario, estimated time to crack)
+    pub crack_times: Vec<(&'static str, String)>,
+}
+
+pub fn process_genpass_check(
+    password: &str,
+    user_inputs: &[&str],
+) -> anyhow::Result<PasswordStrength> {
+    let estimate = zxcvbn(password, user_inputs)?;
+    let (warning, suggestions) = match estimate.feedback() {
+        Some(feedback) => (
+            feedback.warning().map(|w| w.to_string()),
+            feedback
+                .suggestions()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        None => (None, Vec::new()),
+    };
+    let times = estimate.crack_times();
+    let crack_times = vec![
+        (
+            "online, throttled at 100/hour",
+            times.online_throttling_100_per_hour().to_string(),
+        ),
+        (
+            "online, unthrottled at 10/s",
+            times.online_no_throttling_10_per_second().to_string(),
+        ),
+        (
+            "offline, slow hash at 1e4/s",
+            times.offline_slow_hashing_1e4_per_second().to_string(),
+        ),
+        (
+            "offline, fast hash at 1e10/s",
+            times.offline_fast_hashing_1e10_per_second().to_string(),
+        ),
+    ];
+
+    Ok(PasswordStrength {
+        score: estimate.score(),
+        warning,
+        suggestions,
+        crack_times,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_genpass_check() -> anyhow::Result<()> {
+        let weak = process_genpass_check("password", &[])?;
+        assert_eq!(weak.score, 0);
+        assert!(weak.warning.is_some());
+        assert_eq!(weak.crack_times.len(), 4);
+
+        let strong = process_genpass_check("Xz8#qLw!2vRt@9mK", &[])?;
+        assert_eq!(strong.score, 4);
+        Ok(())
     }
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
+
+    #[test]
+    fn test_process_genpass_min_score() -> anyhow::Result<()> {
+        let mut rng = rand::thread_rng();
+        let ret = process_genpass(&mut rng, 16, true, true, true, true, 4)?;
+        assert_eq!(ret.password.len(), 16);
+        assert_eq!(ret.score, 4);
+        // 67 characters in total, a few passwords lack one of the sets
+        assert!(ret.entropy < 16.0 * 67f64.log2());
+        assert!(ret.entropy > 16.0 * 67f64.log2() - 1.0);
+
+        // 4 digits can never be strong
+        assert!(process_genpass(&mut rng, 4, false, false, true, false, 4).is_err());
+        assert!(process_genpass(&mut rng, 16, false, false, false, false, 0).is_err());
+        assert!(process_genpass(&mut rng, 2, true, true, true, true, 0).is_err());
+        Ok(())
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"));
+
+    #[test]
+    fn test_process_genpass_entropy() -> anyhow::Result<()> {
+        // one character of each set, in any of 4! orders
+        let ret = process_genpass(&mut rand::thread_rng(), 4, true, true, true, true, 0)?;
+        let expected = (24.0 * 24.0 * 25.0 * 9.0 * 9.0f64).log2();
+        assert!((ret.entropy - expected).abs() < 1e-9);
+
+        let ret = process_genpass(&mut rand::thread_rng(), 12, false, true, false, false, 0)?;
+        assert!((ret.entropy - 12.0 * 25f64.log2()).abs() < 1e-9);
+        Ok(())
     }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"));
+
+    #[test]
+    fn test_process_genpass_long() -> anyhow::Result<()> {
+        let mut rng = rand::thread_rng();
+        let ret = process_genpass(&mut rng, 1024, true, false, true, false, 0)?;
+        assert_eq!(ret.password.len(), 1024);
+        assert!(ret
+            .password
+            .bytes()
+            .all(|c| UPPER.contains(&c) || NUMBER.contains(&c)));
+
+        // every enabled set is present even at the minimum length
+        let ret = process_genpass(&mut rng, 4, true, true, true, true, 0)?;
+        assert!(ret.password.bytes().any(|c| SYMBOL.contains(&c)));
+        Ok(())
     }
 
-    for _ in 0..(length - password.len() as u8) {
-        let c = chars
-            .choose(&mut rng)
-            .expect("chars won't be empty in this context");
-        password.push(*c);
+    #[test]
+    fn test_process_genbytes() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let bytes = process_genbytes(&mut rng, 64);
+        assert_eq!(bytes.len(), 64);
+        assert_ne!(bytes, process_genbytes(&mut rng, 64));
     }
 
-    password.shuffle(&mut rng);
+    #[test]
+    fn test_process_genpass_seeded() -> anyhow::Result<()> {
+        let mut rng1 = ChaCha20Rng::seed_from_u64(42);
+        let mut rng2 = ChaCha20Rng::seed_from_u64(42);
+        let ret1 = process_genpass(&mut rng1, 24, true, true, true, true, 3)?;
+        let ret2 = process_genpass(&mut rng2, 24, true, true, true, true, 3)?;
+        assert_eq!(ret1.password, ret2.password);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_genpass_derive() -> anyhow::Result<()> {
+        let master = include_bytes!("../../fixtures/blake3.txt");
+        let mut rng = process_genpass_derive_rng(master, "example.com", 1);
+        let ret = process_genpass(&mut rng, 16, true, true, true, true, 0)?;
+
+        // site is normalized, so the same password is derived
+        let mut rng = process_genpass_derive_rng(master, " Example.COM ", 1);
+        let same = process_genpass(&mut rng, 16, true, true, true, true, 0)?;
+        assert_eq!(ret.password, same.password);
 
-    Ok(String::from_utf8(password)?)
+        let mut rng = process_genpass_derive_rng(master, "example.com", 2);
+        let other = process_genpass(&mut rng, 16, true, true, true, true, 0)?;
+        assert_ne!(ret.password, other.password);
+
+        let mut rng = process_genpass_derive_rng(master, "example.org", 1);
+        let other = process_genpass(&mut rng, 16, true, true, true, true, 0)?;
+        assert_ne!(ret.password, other.password);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_genpass_check_user_inputs() -> anyhow::Result<()> {
+        let without = process_genpass_check("tyrchen2024", &[])?;
+        let with = process_genpass_check("tyrchen2024", &["tyrchen"])?;
+        assert!(with.score <= without.score);
+        assert!(process_genpass_check("", &[]).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,389 @@
+use crate::HashAlgorithm;
+use anyhow::Result;
+use data_encoding::HEXLOWER;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::{collections::BTreeMap, fs::File, io::Read, path::Path};
+use walkdir::WalkDir;
+use xxhash_rust::xxh3::Xxh3;
+
+// domain separation of merkle leaves and inner nodes
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+pub trait StreamHasher {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+struct DigestHasher<D>(D);
+
+impl<D: Digest> StreamHasher for DigestHasher<D> {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(&mut self.0, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().to_vec()
+    }
+}
+
+impl StreamHasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        blake3::Hasher::finalize(&self).as_bytes().to_vec()
+    }
+}
+
+impl StreamHasher for Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        Xxh3::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        // canonical (big endian) form, as printed by xxhsum
+        self.digest().to_be_bytes().to_vec()
+    }
+}
+
+/// Deterministic listing of a directory, sorted by path.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HashManifest {
+    /// merkle root over `files`
+    pub root: String,
+    pub files: Vec<HashManifestEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HashManifestEntry {
+    /// relative to the hashed directory, `/` separated
+    pub path: String,
+    pub size: u64,
+    pub blake3: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct HashManifestDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HashCheckStatus {
+    Ok,
+    Failed,
+    /// the file could not be opened or read
+    Unreadable,
+}
+
+fn hasher(algorithm: HashAlgorithm) -> Box<dyn StreamHasher> {
+    match algorithm {
+        HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+        HashAlgorithm::Sha256 => Box::new(DigestHasher(Sha256::new())),
+        HashAlgorithm::Sha512 => Box::new(DigestHasher(Sha512::new())),
+        HashAlgorithm::Sha1 => Box::new(DigestHasher(Sha1::new())),
+        HashAlgorithm::Md5 => Box::new(DigestHasher(Md5::new())),
+        HashAlgorithm::Xxh3 => Box::new(Xxh3::new()),
+    }
+}
+
+/// Stream `reader` through `algorithm` and return the lowercase hex digest.
+pub fn process_hash(reader: &mut dyn Read, algorithm: HashAlgorithm) -> Result<String> {
+    let mut hasher = hasher(algorithm);
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(HEXLOWER.encode(&hasher.finalize()))
+}
+
+/// Verify every `<digest>  <path>` line of a `sha256sum` style checksum file.
+pub fn process_hash_check(
+    reader: &mut dyn Read,
+    algorithm: HashAlgorithm,
+) -> Result<Vec<(String, HashCheckStatus)>> {
+    let mut sums = String::new();
+    reader.read_to_string(&mut sums)?;
+
+    let mut ret = Vec::new();
+    for (i, line) in sums.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (expected, path) = parse_sum_line(line)
+            .ok_or_else(|| anyhow::anyhow!("Invalid checksum line {}: {}", i + 1, line))?;
+        let status = match File::open(path).map(|mut f| process_hash(&mut f, algorithm)) {
+            Ok(Ok(digest)) if digest.eq_ignore_ascii_case(expected) => HashCheckStatus::Ok,
+            Ok(Ok(_)) => HashCheckStatus::Failed,
+            _ => HashCheckStatus::Unreadable,
+        };
+        ret.push((path.to_string(), status));
+    }
+    Ok(ret)
+}
+
+/// Walk `dir` and hash every regular file in it with blake3, except
+/// `exclude`, the manifest itself when it is kept in `dir`. Symlinks are not
+/// followed.
+pub fn process_hash_dir(dir: impl AsRef<Path>, exclude: Option<&Path>) -> Result<HashManifest> {
+    let dir = dir.as_ref();
+    // a manifest that does not exist yet is not in the walk either
+    let exclude = exclude.and_then(|path| path.canonicalize().ok());
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if exclude.as_ref().is_some_and(|exclude| {
+            exclude.file_name() == Some(entry.file_name())
+                && entry
+                    .path()
+                    .canonicalize()
+                    .is_ok_and(|path| &path == exclude)
+        }) {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir)?;
+        let path = path
+            .components()
+            .map(|c| {
+                c.as_os_str()
+                    .to_str()
+                    .ok_or_else(|| anyhow::anyhow!("Non utf-8 path {}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join("/");
+        let size = entry.metadata()?.len();
+        let blake3 = process_hash(&mut File::open(entry.path())?, HashAlgorithm::Blake3)?;
+        files.push(HashManifestEntry { path, size, blake3 });
+    }
+    // walkdir sorts per directory, the manifest is sorted by the full path
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let root = process_hash_merkle_root(&files)?;
+    Ok(HashManifest { root, files })
+}
+
+/// Merkle root of the manifest entries. Leaves are
+/// `blake3(0x00 || u64le(len(path)) || path || u64le(size) || blake3)`, inner
+/// nodes `blake3(0x01 || left || right)`, and an odd node is carried up
+/// unchanged. The root of an empty directory is `blake3("")`.
+pub fn process_hash_merkle_root(files: &[HashManifestEntry]) -> Result<String> {
+    let mut level = files
+        .iter()
+        .map(|f| {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(&[LEAF_PREFIX]);
+            hasher.update(&(f.path.len() as u64).to_le_bytes());
+            hasher.update(f.path.as_bytes());
+            hasher.update(&f.size.to_le_bytes());
+            hasher.update(&HEXLOWER.decode(f.blake3.to_lowercase().as_bytes())?);
+            Ok(hasher.finalize())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if level.is_empty() {
+        return Ok(blake3::hash(b"").to_hex().to_string());
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(&[NODE_PREFIX]);
+                    hasher.update(left.as_bytes());
+                    hasher.update(right.as_bytes());
+                    hasher.finalize()
+                }
+                [odd] => *odd,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    Ok(level[0].to_hex().to_string())
+}
+
+/// Compare a previously generated manifest against the current one.
+pub fn process_hash_manifest_diff(old: &HashManifest, new: &HashManifest) -> HashManifestDiff {
+    let old: BTreeMap<_, _> = old.files.iter().map(|f| (&f.path, f)).collect();
+    let new: BTreeMap<_, _> = new.files.iter().map(|f| (&f.path, f)).collect();
+    let mut diff = HashManifestDiff::default();
+    for (path, entry) in &new {
+        match old.get(path) {
+            None => diff.added.push(path.to_string()),
+            Some(old) if old.size != entry.size || old.blake3 != entry.blake3 => {
+                diff.modified.push(path.to_string())
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed = old
+        .keys()
+        .filter(|path| !new.contains_key(*path))
+        .map(|path| path.to_string())
+        .collect();
+    diff
+}
+
+// "<hex>  <path>" in text mode, "<hex> *<path>" in binary mode
+fn parse_sum_line(line: &str) -> Option<(&str, &str)> {
+    let (digest, rest) = line.split_once(' ')?;
+    let path = rest.strip_prefix([' ', '*'])?;
+    if digest.is_empty() || path.is_empty() || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((digest, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_hash() -> Result<()> {
+        let hash = |algorithm| process_hash(&mut "hello".as_bytes(), algorithm);
+        assert_eq!(
+            hash(HashAlgorithm::Sha256)?,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Sha1)?,
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Md5)?,
+            "5d41402abc4b2a76b9719d911017c592"
+        );
+        assert_eq!(hash(HashAlgorithm::Sha512)?.len(), 128);
+        assert_eq!(
+            hash(HashAlgorithm::Blake3)?,
+            blake3::hash(b"hello").to_hex().as_str()
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Xxh3)?,
+            format!("{:016x}", xxhash_rust::xxh3::xxh3_64(b"hello"))
+        );
+
+        // larger than the read buffer
+        let input = vec![7u8; 200 * 1024];
+        assert_eq!(
+            process_hash(&mut &input[..], HashAlgorithm::Blake3)?,
+            blake3::hash(&input).to_hex().as_str()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_hash_check() -> Result<()> {
+        let digest = process_hash(&mut File::open("fixtures/b64.txt")?, HashAlgorithm::Sha256)?;
+        let sums = format!(
+            "{}  fixtures/b64.txt\n{} *fixtures/blake3.txt\n{}  fixtures/missing.txt\n",
+            digest, digest, digest
+        );
+        let ret = process_hash_check(&mut sums.as_bytes(), HashAlgorithm::Sha256)?;
+        assert_eq!(
+            ret,
+            vec![
+                ("fixtures/b64.txt".to_string(), HashCheckStatus::Ok),
+                ("fixtures/blake3.txt".to_string(), HashCheckStatus::Failed),
+                (
+                    "fixtures/missing.txt".to_string(),
+                    HashCheckStatus::Unreadable
+                ),
+            ]
+        );
+
+        let invalid = "not a checksum line";
+        assert!(process_hash_check(&mut invalid.as_bytes(), HashAlgorithm::Sha256).is_err());
+        Ok(())
+    }
+
+    fn entry(path: &str, content: &[u8]) -> HashManifestEntry {
+        HashManifestEntry {
+            path: path.to_string(),
+            size: content.len() as u64,
+            blake3: blake3::hash(content).to_hex().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_process_hash_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("sub"))?;
+        for (path, content) in [("b.txt", "b"), ("a.txt", "hello"), ("sub/c.txt", "")] {
+            std::fs::write(dir.path().join(path), content)?;
+        }
+        let manifest = process_hash_dir(dir.path(), None)?;
+        assert_eq!(
+            manifest.files,
+            [
+                entry("a.txt", b"hello"),
+                entry("b.txt", b"b"),
+                entry("sub/c.txt", b"")
+            ]
+        );
+        assert_eq!(manifest.root, process_hash_merkle_root(&manifest.files)?);
+
+        // a manifest written into the directory does not count itself
+        let output = dir.path().join("manifest.json");
+        assert_eq!(manifest, process_hash_dir(dir.path(), Some(&output))?);
+        std::fs::write(&output, serde_json::to_vec(&manifest)?)?;
+        assert_eq!(manifest, process_hash_dir(dir.path(), Some(&output))?);
+        assert_eq!(process_hash_dir(dir.path(), None)?.files.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_hash_merkle_root() -> Result<()> {
+        let files: Vec<_> = (0..5).map(|i| entry(&format!("f{}", i), &[i])).collect();
+        let root = process_hash_merkle_root(&files)?;
+        assert_ne!(root, process_hash_merkle_root(&files[..4])?);
+        assert_eq!(
+            process_hash_merkle_root(&[])?,
+            blake3::hash(b"").to_hex().as_str()
+        );
+
+        // a renamed file changes the root even if the content is the same
+        let mut renamed: Vec<_> = (0..5).map(|i| entry(&format!("f{}", i), &[i])).collect();
+        renamed[4].path = "g4".to_string();
+        assert_ne!(root, process_hash_merkle_root(&renamed)?);
+
+        // a leaf can not pass for an inner node
+        let leaf = process_hash_merkle_root(&files[..1])?;
+        assert_ne!(
+            leaf,
+            blake3::hash(files[0].path.as_bytes()).to_hex().as_str()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_hash_manifest_diff() -> Result<()> {
+        let manifest = |files: Vec<HashManifestEntry>| -> Result<HashManifest> {
+            let root = process_hash_merkle_root(&files)?;
+            Ok(HashManifest { root, files })
+        };
+        let old = manifest(vec![entry("a", b"a"), entry("b", b"b"), entry("c", b"c")])?;
+        let new = manifest(vec![entry("a", b"a"), entry("c", b"c2"), entry("d", b"d")])?;
+        assert_eq!(
+            process_hash_manifest_diff(&old, &new),
+            HashManifestDiff {
+                added: vec!["d".to_string()],
+                removed: vec!["b".to_string()],
+                modified: vec!["c".to_string()],
+            }
+        );
+        assert_eq!(process_hash_manifest_diff(&old, &old), Default::default());
+        Ok(())
+    }
+}
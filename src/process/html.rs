@@ -0,0 +1,36 @@
+use crate::read_text;
+use anyhow::Result;
+use std::io::Read;
+
+/// Escape `&`, `<`, `>`, `"`, `'` and `/` as HTML entities.
+pub fn process_html_escape(reader: &mut dyn Read) -> Result<String> {
+    let input = read_text(reader)?;
+    Ok(html_escape::encode_safe(&input).into_owned())
+}
+
+/// Decode named (`&amp;`) and numeric (`&#39;`, `&#x27;`) HTML entities.
+pub fn process_html_unescape(reader: &mut dyn Read) -> Result<String> {
+    let input = read_text(reader)?;
+    Ok(html_escape::decode_html_entities(&input).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_html() -> Result<()> {
+        let input = r#"<a href="/x?a=1&b=2">Tom's</a>"#;
+        let escaped = process_html_escape(&mut input.as_bytes())?;
+        assert_eq!(
+            escaped,
+            "&lt;a href=&quot;&#x2F;x?a=1&amp;b=2&quot;&gt;Tom&#x27;s&lt;&#x2F;a&gt;"
+        );
+        assert_eq!(process_html_unescape(&mut escaped.as_bytes())?, input);
+        assert_eq!(
+            process_html_unescape(&mut "&copy; &#169; &#xa9; &nbsp;".as_bytes())?,
+            "© © © \u{a0}"
+        );
+        Ok(())
+    }
+}
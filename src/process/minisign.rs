@@ -0,0 +1,343 @@
+use super::{
+    key::{load_signing_key, load_verifying_key, KeyError},
+    text::{TextSigner, TextVerifier},
+};
+use crate::TextSignFormat;
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::io::{self, Read};
+
+// minisign and signify keys and signatures are a base64 line of a 2 byte
+// algorithm, an 8 byte key id and the key or signature, below an untrusted
+// comment. "Ed" signs the input itself, "ED" its BLAKE2b-512. minisign adds
+// a trusted comment, signed together with the signature.
+const ALG_PURE: &[u8; 2] = b"Ed";
+const ALG_HASHED: &[u8; 2] = b"ED";
+const UNTRUSTED_COMMENT: &str = "untrusted comment:";
+const TRUSTED_COMMENT: &str = "trusted comment: ";
+
+pub struct MinisignSigner {
+    key: SigningKey,
+}
+
+pub struct MinisignVerifier {
+    key: VerifyingKey,
+}
+
+pub struct SignifyVerifier {
+    key: VerifyingKey,
+}
+
+/// Result of verifying a minisign or signify signature file.
+#[derive(Debug)]
+pub struct MinisignVerification {
+    /// minisign if the file has a trusted comment, signify otherwise
+    pub format: TextSignFormat,
+    pub verified: bool,
+    /// key id of the signature, not verified if the public key has another
+    pub key_id: String,
+    pub trusted_comment: Option<String>,
+}
+
+impl TextSigner for MinisignSigner {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        Ok(self.key.sign(&blake2b(reader)?).to_bytes().to_vec())
+    }
+}
+
+impl TextVerifier for MinisignVerifier {
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let signature = signature(sig)?;
+        Ok(self.key.verify(&blake2b(reader)?, &signature).is_ok())
+    }
+}
+
+impl TextVerifier for SignifyVerifier {
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let signature = signature(sig)?;
+        // signify signs the input itself, it can not be streamed
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(self.key.verify(&buf, &signature).is_ok())
+    }
+}
+
+impl MinisignSigner {
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = load_signing_key(key.as_ref())?;
+        Ok(Self { key })
+    }
+}
+
+impl MinisignVerifier {
+    /// Accepts minisign public keys and all formats of ed25519 public keys.
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let (_, key) = load_public_key(key.as_ref())?;
+        Ok(Self { key })
+    }
+}
+
+impl SignifyVerifier {
+    /// Accepts signify public keys and all formats of ed25519 public keys.
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let (_, key) = load_public_key(key.as_ref())?;
+        Ok(Self { key })
+    }
+}
+
+/// Sign into the content of a minisign `.minisig` file. `trusted_comment`
+/// is signed along with the signature.
+pub fn process_text_sign_minisign(
+    reader: &mut dyn Read,
+    key: &[u8],
+    trusted_comment: &str,
+) -> Result<String> {
+    if trusted_comment.contains(['\r', '\n']) {
+        anyhow::bail!("The trusted comment must be a single line");
+    }
+    let signer = MinisignSigner::try_new(key)?;
+    let key_id = minisign_key_id(&signer.key.verifying_key());
+    let sig = signer.sign(reader)?;
+    let global_sig = signer
+        .key
+        .sign(&[&sig, trusted_comment.as_bytes()].concat());
+    Ok(format!(
+        "{} signature from rcli secret key {}\n{}\n{}{}\n{}\n",
+        UNTRUSTED_COMMENT,
+        key_id_hex(&key_id),
+        STANDARD.encode([ALG_HASHED.as_slice(), &key_id, &sig].concat()),
+        TRUSTED_COMMENT,
+        trusted_comment,
+        STANDARD.encode(global_sig.to_bytes())
+    ))
+}
+
+/// Verify a minisign `.minisig` or an OpenBSD signify `.sig` file against
+/// `key`, a minisign/signify public key or any ed25519 public key.
+pub fn process_text_verify_minisign(
+    reader: &mut dyn Read,
+    key: &[u8],
+    sig_file: &str,
+) -> Result<MinisignVerification> {
+    let lines: Vec<&str> = sig_file.lines().map(|l| l.trim_end_matches('\r')).collect();
+    let (sig_line, trusted) = match lines.as_slice() {
+        [untrusted, sig] if untrusted.starts_with(UNTRUSTED_COMMENT) => (*sig, None),
+        [untrusted, sig, trusted, global_sig, ..]
+            if untrusted.starts_with(UNTRUSTED_COMMENT) && trusted.starts_with(TRUSTED_COMMENT) =>
+        {
+            (*sig, Some((&trusted[TRUSTED_COMMENT.len()..], *global_sig)))
+        }
+        _ => anyhow::bail!("Not a minisign or signify signature file"),
+    };
+    let blob = STANDARD
+        .decode(sig_line)
+        .map_err(|e| KeyError::encoding("minisign signature", e))?;
+    if blob.len() != 2 + 8 + Signature::BYTE_SIZE {
+        return Err(KeyError::SignatureLength {
+            kind: "minisign",
+            expected: 2 + 8 + Signature::BYTE_SIZE,
+            actual: blob.len(),
+        }
+        .into());
+    }
+    let (alg, rest) = blob.split_at(2);
+    let (sig_key_id, sig) = rest.split_at(8);
+
+    let (key_id, key) = load_public_key(key)?;
+    let mut verified = key_id.is_none_or(|key_id| key_id == sig_key_id);
+    verified &= match alg {
+        _ if alg == ALG_HASHED => MinisignVerifier { key }.verify(reader, sig)?,
+        _ if alg == ALG_PURE => SignifyVerifier { key }.verify(reader, sig)?,
+        _ => anyhow::bail!("Unsupported signature algorithm {:?}", alg),
+    };
+
+    let format = match trusted {
+        Some(_) => TextSignFormat::Minisign,
+        None => TextSignFormat::Signify,
+    };
+    let trusted_comment = match trusted {
+        Some((comment, global_sig)) => {
+            let global_sig = STANDARD
+                .decode(global_sig)
+                .map_err(|e| KeyError::encoding("minisign global signature", e))?;
+            let global_sig = signature(&global_sig)?;
+            verified &= key
+                .verify(&[sig, comment.as_bytes()].concat(), &global_sig)
+                .is_ok();
+            Some(comment.to_string())
+        }
+        None => None,
+    };
+    Ok(MinisignVerification {
+        format,
+        verified,
+        key_id: key_id_hex(sig_key_id.try_into()?),
+        trusted_comment,
+    })
+}
+
+/// Content of a minisign public key file for `key`.
+pub(crate) fn minisign_public_key(key: &VerifyingKey) -> String {
+    let key_id = minisign_key_id(key);
+    let blob = [ALG_PURE.as_slice(), &key_id, key.as_bytes()].concat();
+    format!(
+        "{} minisign public key {}\n{}\n",
+        UNTRUSTED_COMMENT,
+        key_id_hex(&key_id),
+        STANDARD.encode(blob)
+    )
+}
+
+/// Key id as minisign prints it, from the public key file if there is one.
+pub(crate) fn minisign_fingerprint(key: &[u8]) -> Result<String> {
+    let (key_id, key) = load_public_key(key)?;
+    Ok(key_id_hex(&key_id.unwrap_or_else(|| minisign_key_id(&key))))
+}
+
+pub(crate) fn is_minisign_public_key(text: &str) -> bool {
+    // base64 of "Ed" is "RW"
+    text.starts_with(UNTRUSTED_COMMENT) || (text.len() == 56 && text.starts_with("RW"))
+}
+
+/// Parse a minisign/signify public key file, or only its base64 line.
+pub(crate) fn parse_minisign_public_key(text: &str) -> Result<([u8; 8], VerifyingKey), KeyError> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with(UNTRUSTED_COMMENT))
+        .unwrap_or_default();
+    let blob = STANDARD
+        .decode(line)
+        .map_err(|e| KeyError::encoding("minisign public key", e))?;
+    if blob.len() != 2 + 8 + 32 || !blob.starts_with(ALG_PURE) {
+        return Err(KeyError::encoding(
+            "minisign public key",
+            "not an ed25519 public key",
+        ));
+    }
+    let key_id = blob[2..10].try_into().unwrap();
+    let key = VerifyingKey::from_bytes(blob[10..].try_into().unwrap())
+        .map_err(|_| KeyError::InvalidPoint)?;
+    Ok((key_id, key))
+}
+
+// public key with its minisign key id, if the key file carries one
+fn load_public_key(key: &[u8]) -> Result<(Option<[u8; 8]>, VerifyingKey), KeyError> {
+    let text = std::str::from_utf8(key).unwrap_or_default().trim();
+    if is_minisign_public_key(text) {
+        let (key_id, key) = parse_minisign_public_key(text)?;
+        return Ok((Some(key_id), key));
+    }
+    Ok((None, load_verifying_key(key)?))
+}
+
+// minisign picks a random key id, ours is derived from the key so it is stable
+fn minisign_key_id(key: &VerifyingKey) -> [u8; 8] {
+    Blake2b512::digest(key.as_bytes())[..8].try_into().unwrap()
+}
+
+fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+fn signature(sig: &[u8]) -> Result<Signature, KeyError> {
+    Signature::from_slice(sig).map_err(|_| KeyError::SignatureLength {
+        kind: "ed25519",
+        expected: Signature::BYTE_SIZE,
+        actual: sig.len(),
+    })
+}
+
+fn blake2b(reader: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut hasher = Blake2b512::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SK: &[u8] = include_bytes!("../../fixtures/ed25519.sk");
+    const PK: &[u8] = include_bytes!("../../fixtures/ed25519.pk");
+
+    #[test]
+    fn test_process_text_minisign() -> Result<()> {
+        let minisig = process_text_sign_minisign(&mut "hello".as_bytes(), SK, "file:hello.txt")?;
+        let lines: Vec<_> = minisig.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(STANDARD.decode(lines[1])?.starts_with(ALG_HASHED));
+        assert_eq!(lines[2], "trusted comment: file:hello.txt");
+
+        let pk = minisign_public_key(&load_verifying_key(PK)?);
+        for key in [pk.as_bytes(), PK] {
+            let ret = process_text_verify_minisign(&mut "hello".as_bytes(), key, &minisig)?;
+            assert!(ret.verified);
+            assert!(matches!(ret.format, TextSignFormat::Minisign));
+            assert_eq!(ret.trusted_comment.as_deref(), Some("file:hello.txt"));
+        }
+
+        let ret = process_text_verify_minisign(&mut "hallo".as_bytes(), PK, &minisig)?;
+        assert!(!ret.verified);
+        let tampered = minisig.replace("hello.txt", "hello.exe");
+        let ret = process_text_verify_minisign(&mut "hello".as_bytes(), PK, &tampered)?;
+        assert!(!ret.verified);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_verify_minisign_fixture() -> Result<()> {
+        // minisign.txt.minisig was made by `minisign -S` (minisign-verify's
+        // test vector), minisign.txt.sig is its legacy signature without the
+        // trusted comment, which is what `signify -S` writes for that key
+        let pk = include_str!("../../fixtures/minisign.pub");
+        let minisig = include_str!("../../fixtures/minisign.txt.minisig");
+        let sig = include_str!("../../fixtures/minisign.txt.sig");
+        assert_eq!(minisign_fingerprint(pk.as_bytes())?, "E7620F1842B4E81F");
+
+        let ret = process_text_verify_minisign(&mut "test".as_bytes(), pk.as_bytes(), minisig)?;
+        assert!(ret.verified);
+        assert!(matches!(ret.format, TextSignFormat::Minisign));
+        assert_eq!(
+            ret.trusted_comment.as_deref(),
+            Some("timestamp:1556193335\tfile:test")
+        );
+        let ret = process_text_verify_minisign(&mut "test".as_bytes(), pk.as_bytes(), sig)?;
+        assert!(ret.verified);
+        assert!(matches!(ret.format, TextSignFormat::Signify));
+
+        for sig_file in [minisig, sig] {
+            let ret =
+                process_text_verify_minisign(&mut "Test".as_bytes(), pk.as_bytes(), sig_file)?;
+            assert!(!ret.verified);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_verify_signify() -> Result<()> {
+        let sk = load_signing_key(SK)?;
+        let pk = minisign_public_key(&sk.verifying_key());
+        let (key_id, _) = parse_minisign_public_key(&pk)?;
+        let sig = sk.sign(b"hello").to_bytes();
+        let sig_file = format!(
+            "untrusted comment: verify with rcli.pub\n{}\n",
+            STANDARD.encode([ALG_PURE.as_slice(), &key_id, &sig].concat())
+        );
+        let ret = process_text_verify_minisign(&mut "hello".as_bytes(), pk.as_bytes(), &sig_file)?;
+        assert!(ret.verified);
+        assert!(matches!(ret.format, TextSignFormat::Signify));
+        assert!(ret.trusted_comment.is_none());
+
+        // the key id of the signature has to match the public key file
+        let other = minisign_public_key(&SigningKey::from_bytes(&[7; 32]).verifying_key());
+        let ret =
+            process_text_verify_minisign(&mut "hello".as_bytes(), other.as_bytes(), &sig_file)?;
+        assert!(!ret.verified);
+        assert_eq!(ret.key_id, minisign_fingerprint(pk.as_bytes())?);
+        assert!(process_text_verify_minisign(&mut "hello".as_bytes(), PK, "hello").is_err());
+        Ok(())
+    }
+}
@@ -1,11 +1,57 @@
 mod b64;
+mod codec;
 mod csv_convert;
 mod gen_pass;
+mod hash;
+mod html;
 mod http_serve;
+mod jwt;
+mod key;
+mod minisign;
+mod otp;
+mod pem;
+mod sshsig;
 mod text;
+mod url;
 
-pub use b64::{process_decode, process_encode};
+pub use b64::{
+    process_base64_detect, process_decode, process_encode, process_encode_data_uri,
+    MIME_LINE_WIDTH, PEM_LINE_WIDTH,
+};
+pub use codec::{process_codec_decode, process_codec_detect, process_codec_encode};
 pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use gen_pass::{
+    process_genbytes, process_genpass, process_genpass_check, process_genpass_derive_rng, GenPass,
+    PasswordStrength,
+};
+pub use hash::{
+    process_hash, process_hash_check, process_hash_dir, process_hash_manifest_diff,
+    process_hash_merkle_root, HashCheckStatus, HashManifest, HashManifestDiff, HashManifestEntry,
+    StreamHasher,
+};
+pub use html::{process_html_escape, process_html_unescape};
 pub use http_serve::process_http_serve;
-pub use text::{process_text_key_generate, process_text_sign, process_text_verify};
+pub use jwt::{
+    process_jwt_decode, process_jwt_sign, process_jwt_verify, JwtAudience, JwtClaims, JwtValidation,
+};
+pub use key::{process_text_key_export, KeyError};
+pub use minisign::{
+    process_text_sign_minisign, process_text_verify_minisign, MinisignVerification,
+};
+pub use otp::{
+    process_hotp, process_otp_decode_secret, process_otp_qr, process_otp_secret, process_otp_uri,
+    process_totp,
+};
+pub use pem::{process_pem_unwrap, process_pem_wrap};
+pub use sshsig::{
+    process_text_sign_sshsig, process_text_verify_allowed_signers, process_text_verify_sshsig,
+    SshSigVerification, SSHSIG_DEFAULT_NAMESPACE,
+};
+pub use text::{
+    process_text_decrypt, process_text_decrypt_identity, process_text_decrypt_passphrase,
+    process_text_encrypt, process_text_encrypt_passphrase, process_text_encrypt_recipients,
+    process_text_key_fingerprint, process_text_key_generate, process_text_sign,
+    process_text_sign_envelope, process_text_verify, process_text_verify_envelope,
+    SignatureEnvelope,
+};
+pub use url::{process_url_decode, process_url_encode};
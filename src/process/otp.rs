@@ -0,0 +1,184 @@
+use crate::{process::url::COMPONENT, process_genbytes, OtpAlgorithm};
+use anyhow::Result;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::utf8_percent_encode;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use rand::{CryptoRng, RngCore};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+/// Generate a random TOTP secret of `length` bytes, base32 encoded without padding.
+pub fn process_otp_secret<R: RngCore + CryptoRng>(rng: &mut R, length: usize) -> String {
+    BASE32_NOPAD.encode(&process_genbytes(rng, length))
+}
+
+/// Build a Key URI Format `otpauth://totp/...` uri understood by authenticator apps.
+pub fn process_otp_uri(
+    secret: &str,
+    issuer: &str,
+    account: &str,
+    algorithm: OtpAlgorithm,
+    digits: u32,
+    period: u64,
+) -> String {
+    let issuer = utf8_percent_encode(issuer, COMPONENT).to_string();
+    let account = utf8_percent_encode(account, COMPONENT);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm={}&digits={}&period={}",
+        issuer,
+        account,
+        secret,
+        issuer,
+        algorithm.to_string().to_uppercase(),
+        digits,
+        period
+    )
+}
+
+/// Render `data` as a QR code made of unicode half blocks for the terminal.
+pub fn process_otp_qr(data: &str) -> Result<String> {
+    let code = QrCode::new(data)?;
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    Ok(image)
+}
+
+/// Decode a base32 secret the way users tend to paste it: any case, with
+/// spaces and with or without padding.
+pub fn process_otp_decode_secret(secret: &str) -> Result<Vec<u8>> {
+    let secret: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>()
+        .to_uppercase();
+    Ok(BASE32_NOPAD.decode(secret.as_bytes())?)
+}
+
+/// RFC 4226 HOTP code for `counter`.
+pub fn process_hotp(
+    secret: &[u8],
+    counter: u64,
+    digits: u32,
+    algorithm: OtpAlgorithm,
+) -> Result<String> {
+    let hash = hmac(secret, &counter.to_be_bytes(), algorithm)?;
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes(hash[offset..offset + 4].try_into()?) & 0x7fff_ffff;
+    let code = code as u64 % 10u64.pow(digits);
+    Ok(format!("{:0width$}", code, width = digits as usize))
+}
+
+/// RFC 6238 TOTP code for unix time `time`. `period` is at least 1 second.
+pub fn process_totp(
+    secret: &[u8],
+    time: u64,
+    period: u64,
+    digits: u32,
+    algorithm: OtpAlgorithm,
+) -> Result<String> {
+    if period == 0 {
+        anyhow::bail!("The period must be at least 1 second");
+    }
+    process_hotp(secret, time / period, digits, algorithm)
+}
+
+fn hmac(key: &[u8], data: &[u8], algorithm: OtpAlgorithm) -> Result<Vec<u8>> {
+    let ret = match algorithm {
+        OtpAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(key)?;
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        }
+        OtpAlgorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        }
+        OtpAlgorithm::Sha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // test vectors from RFC 4226 appendix D and RFC 6238 appendix B
+    const SEED: &[u8] = b"12345678901234567890";
+    const SEED32: &[u8] = b"12345678901234567890123456789012";
+    const SEED64: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    #[test]
+    fn test_process_hotp() -> Result<()> {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(
+                process_hotp(SEED, counter as u64, 6, OtpAlgorithm::Sha1)?,
+                *code
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_totp() -> Result<()> {
+        assert_eq!(
+            process_totp(SEED, 59, 30, 8, OtpAlgorithm::Sha1)?,
+            "94287082"
+        );
+        assert_eq!(
+            process_totp(SEED32, 59, 30, 8, OtpAlgorithm::Sha256)?,
+            "46119246"
+        );
+        assert_eq!(
+            process_totp(SEED64, 59, 30, 8, OtpAlgorithm::Sha512)?,
+            "90693936"
+        );
+        assert_eq!(
+            process_totp(SEED, 1111111109, 30, 8, OtpAlgorithm::Sha1)?,
+            "07081804"
+        );
+        assert!(process_totp(SEED, 59, 0, 8, OtpAlgorithm::Sha1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_otp_secret() -> Result<()> {
+        let secret = process_otp_secret(&mut rand::thread_rng(), 20);
+        assert_eq!(secret.len(), 32);
+        let decoded = process_otp_decode_secret(&secret.to_lowercase())?;
+        assert_eq!(decoded.len(), 20);
+
+        let decoded = process_otp_decode_secret("GEZD GNBV GY3T QOJQ GEZD GNBV GY3T QOJQ")?;
+        assert_eq!(decoded, SEED);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_otp_uri() {
+        let uri = process_otp_uri(
+            "JBSWY3DPEHPK3PXP",
+            "ACME Co",
+            "john@example.com",
+            OtpAlgorithm::Sha1,
+            6,
+            30,
+        );
+        assert_eq!(
+            uri,
+            "otpauth://totp/ACME%20Co:john%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use super::{
+    key::{ed25519_fingerprint, load_signing_key, load_verifying_key, KeyError},
+    text::{TextSigner, TextVerifier},
+};
+use anyhow::Result;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256, Sha512};
+use ssh_key::{public::Ed25519PublicKey, Algorithm, HashAlg, LineEnding, SshSig};
+use std::{
+    io::{self, Read},
+    time::SystemTime,
+};
+use tracing::warn;
+
+/// Namespace `ssh-keygen -Y sign -n file` uses for signing files. git uses "git".
+pub const SSHSIG_DEFAULT_NAMESPACE: &str = "file";
+
+const MAGIC_PREAMBLE: &[u8] = b"SSHSIG";
+
+pub struct SshSigSigner {
+    key: SigningKey,
+    namespace: String,
+}
+
+pub struct SshSigVerifier {
+    key: VerifyingKey,
+    namespace: String,
+}
+
+/// Result of verifying a SSH signature against a key or an allowed signers
+/// file. A signature by another key or for another namespace is not verified.
+#[derive(Debug)]
+pub struct SshSigVerification {
+    pub verified: bool,
+    /// `SHA256:...` fingerprint of the key that made the signature
+    pub fingerprint: String,
+    /// namespace the signature was made for
+    pub namespace: String,
+    /// principals of the allowed signers entries that accepted the key
+    pub principals: Vec<String>,
+}
+
+// one line of an allowed signers file, see ssh-keygen(1) ALLOWED SIGNERS
+#[derive(Debug)]
+struct AllowedSigner {
+    principals: String,
+    namespaces: Option<String>,
+    valid_after: Option<SystemTime>,
+    valid_before: Option<SystemTime>,
+    cert_authority: bool,
+    key: ssh_key::PublicKey,
+}
+
+impl TextSigner for SshSigSigner {
+    /// The armored `-----BEGIN SSH SIGNATURE-----` signature.
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let hash_alg = HashAlg::Sha512;
+        let signed_data = signed_data(&self.namespace, &[], hash_alg, &digest(reader, hash_alg)?);
+        let signature = self.key.sign(&signed_data);
+        let public_key = Ed25519PublicKey::from(&self.key.verifying_key());
+        let signature = ssh_key::Signature::new(Algorithm::Ed25519, signature.to_bytes())?;
+        let sshsig = SshSig::new(public_key.into(), &self.namespace, hash_alg, signature)?;
+        Ok(sshsig.to_pem(LineEnding::LF)?.into_bytes())
+    }
+}
+
+impl TextVerifier for SshSigVerifier {
+    /// `sig` is the armored signature. Not verified if it was made by
+    /// another key or for another namespace.
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let sshsig = parse_sshsig(sig)?;
+        if sshsig_key(&sshsig)? != self.key || sshsig.namespace() != self.namespace {
+            return Ok(false);
+        }
+        let Ok(signature) = Signature::from_slice(sshsig.signature_bytes()) else {
+            return Err(KeyError::SignatureLength {
+                kind: "ed25519",
+                expected: Signature::BYTE_SIZE,
+                actual: sshsig.signature_bytes().len(),
+            }
+            .into());
+        };
+        let hash = digest(reader, sshsig.hash_alg())?;
+        let signed_data = signed_data(
+            sshsig.namespace(),
+            sshsig.reserved(),
+            sshsig.hash_alg(),
+            &hash,
+        );
+        Ok(self.key.verify(&signed_data, &signature).is_ok())
+    }
+}
+
+impl SshSigSigner {
+    /// Accepts the same ed25519 keys as `Ed25519Signer`, e.g. `id_ed25519`.
+    pub fn try_new(key: impl AsRef<[u8]>, namespace: &str) -> Result<Self> {
+        let key = load_signing_key(key.as_ref())?;
+        Ok(Self {
+            key,
+            namespace: check_namespace(namespace)?,
+        })
+    }
+}
+
+impl SshSigVerifier {
+    /// Accepts the same ed25519 keys as `Ed25519Verifier`, e.g. `id_ed25519.pub`.
+    pub fn try_new(key: impl AsRef<[u8]>, namespace: &str) -> Result<Self> {
+        let key = load_verifying_key(key.as_ref())?;
+        Ok(Self {
+            key,
+            namespace: check_namespace(namespace)?,
+        })
+    }
+}
+
+/// Sign like `ssh-keygen -Y sign -n <namespace>`.
+pub fn process_text_sign_sshsig(
+    reader: &mut dyn Read,
+    key: &[u8],
+    namespace: &str,
+) -> Result<String> {
+    let signer = SshSigSigner::try_new(key, namespace)?;
+    Ok(String::from_utf8(signer.sign(reader)?)?)
+}
+
+/// Verify that `key` made the signature for `namespace`, without an allowed
+/// signers file. The result names the key and namespace of the signature.
+pub fn process_text_verify_sshsig(
+    reader: &mut dyn Read,
+    key: &[u8],
+    sig: &str,
+    namespace: &str,
+) -> Result<SshSigVerification> {
+    let verifier = SshSigVerifier::try_new(key, namespace)?;
+    let sshsig = parse_sshsig(sig.as_bytes())?;
+    Ok(SshSigVerification {
+        verified: verifier.verify(reader, sig.as_bytes())?,
+        fingerprint: ed25519_fingerprint(&sshsig_key(&sshsig)?),
+        namespace: sshsig.namespace().to_string(),
+        principals: Vec::new(),
+    })
+}
+
+/// Verify like `ssh-keygen -Y verify` against an allowed signers file. The
+/// key of the signature must be listed for `namespace`, valid at `now` and,
+/// if given, for `principal`, otherwise the signature is not verified.
+/// Without a principal all principals listing the key are returned, like
+/// `ssh-keygen -Y find-principals`.
+pub fn process_text_verify_allowed_signers(
+    reader: &mut dyn Read,
+    allowed_signers: &str,
+    sig: &str,
+    namespace: &str,
+    principal: Option<&str>,
+    now: SystemTime,
+) -> Result<SshSigVerification> {
+    let sshsig = parse_sshsig(sig.as_bytes())?;
+    let key = sshsig_key(&sshsig)?;
+    let fingerprint = ed25519_fingerprint(&key);
+
+    let mut principals = Vec::new();
+    for (i, line) in allowed_signers.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // like ssh-keygen, a line it can not use does not spoil the others
+        let signer = match parse_allowed_signer(line) {
+            Ok(signer) => signer,
+            Err(e) => {
+                warn!("Skipping allowed signers line {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        // certificates are not supported, so neither are their authorities
+        if signer.cert_authority || signer.key.key_data().ed25519() != sshsig.public_key().ed25519()
+        {
+            continue;
+        }
+        if let Some(namespaces) = &signer.namespaces {
+            if !match_pattern_list(namespace, namespaces) {
+                continue;
+            }
+        }
+        if signer.valid_after.is_some_and(|t| now < t)
+            || signer.valid_before.is_some_and(|t| now > t)
+        {
+            continue;
+        }
+        match principal {
+            Some(principal) if match_pattern_list(principal, &signer.principals) => {
+                principals.push(principal.to_string())
+            }
+            Some(_) => {}
+            None => principals.extend(
+                signer
+                    .principals
+                    .split(',')
+                    .filter(|p| !p.starts_with('!'))
+                    .map(String::from),
+            ),
+        }
+    }
+    let verifier = SshSigVerifier {
+        key,
+        namespace: check_namespace(namespace)?,
+    };
+    let verified = !principals.is_empty() && verifier.verify(reader, sig.as_bytes())?;
+    Ok(SshSigVerification {
+        verified,
+        fingerprint,
+        namespace: sshsig.namespace().to_string(),
+        principals,
+    })
+}
+
+fn parse_sshsig(sig: &[u8]) -> Result<SshSig, KeyError> {
+    SshSig::from_pem(sig).map_err(|e| KeyError::encoding("SSH signature", e))
+}
+
+fn sshsig_key(sshsig: &SshSig) -> Result<VerifyingKey, KeyError> {
+    let key = sshsig
+        .public_key()
+        .ed25519()
+        .ok_or_else(|| KeyError::encoding("SSH signature", "not an ed25519 signature"))?;
+    VerifyingKey::from_bytes(&key.0).map_err(|_| KeyError::InvalidPoint)
+}
+
+fn check_namespace(namespace: &str) -> Result<String> {
+    if namespace.is_empty() {
+        anyhow::bail!("The namespace must not be empty");
+    }
+    Ok(namespace.to_string())
+}
+
+// the blob that is signed, see PROTOCOL.sshsig in OpenSSH
+fn signed_data(namespace: &str, reserved: &[u8], hash_alg: HashAlg, hash: &[u8]) -> Vec<u8> {
+    let mut data = MAGIC_PREAMBLE.to_vec();
+    for field in [
+        namespace.as_bytes(),
+        reserved,
+        hash_alg.as_str().as_bytes(),
+        hash,
+    ] {
+        data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        data.extend_from_slice(field);
+    }
+    data
+}
+
+fn digest(reader: &mut dyn Read, hash_alg: HashAlg) -> Result<Vec<u8>> {
+    let hash = match hash_alg {
+        HashAlg::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(reader, &mut hasher)?;
+            hasher.finalize().to_vec()
+        }
+        HashAlg::Sha512 => {
+            let mut hasher = Sha512::new();
+            io::copy(reader, &mut hasher)?;
+            hasher.finalize().to_vec()
+        }
+        _ => anyhow::bail!("Unsupported hash algorithm {}", hash_alg),
+    };
+    Ok(hash)
+}
+
+// principals [options] keytype base64 [comment]
+fn parse_allowed_signer(line: &str) -> Result<AllowedSigner> {
+    let (principals, rest) = split_token(line);
+    let principals = principals.trim_matches('"').to_string();
+    let (options, key) = match split_token(rest) {
+        (token, _)
+            if ["ssh-", "ecdsa-", "sk-"]
+                .iter()
+                .any(|t| token.starts_with(t)) =>
+        {
+            ("", rest)
+        }
+        (options, key) => (options, key),
+    };
+
+    let mut signer = AllowedSigner {
+        principals,
+        namespaces: None,
+        valid_after: None,
+        valid_before: None,
+        cert_authority: false,
+        key: ssh_key::PublicKey::from_openssh(key)?,
+    };
+    for option in split_options(options) {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value.trim_matches('"'))),
+            None => (option, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), value) {
+            ("cert-authority", None) => signer.cert_authority = true,
+            ("namespaces", Some(value)) => signer.namespaces = Some(value.to_string()),
+            ("valid-after", Some(value)) => signer.valid_after = Some(parse_time(value)?),
+            ("valid-before", Some(value)) => signer.valid_before = Some(parse_time(value)?),
+            _ => anyhow::bail!("unsupported option {}", option),
+        }
+    }
+    Ok(signer)
+}
+
+// first whitespace separated token, double quotes may contain spaces
+fn split_token(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return (&s[..i], s[i..].trim_start()),
+            _ => {}
+        }
+    }
+    (s, "")
+}
+
+fn split_options(options: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in options.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                ret.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < options.len() {
+        ret.push(&options[start..]);
+    }
+    ret
+}
+
+// YYYYMMDD[HHMM[SS]]Z. ssh-keygen takes times without Z as local time,
+// they are rejected rather than read in another time zone.
+fn parse_time(time: &str) -> Result<SystemTime> {
+    let Some(t) = time.strip_suffix(['Z', 'z']) else {
+        anyhow::bail!(
+            "time {} is local time, only UTC times ending in Z are supported",
+            time
+        );
+    };
+    if ![8, 12, 14].contains(&t.len()) || !t.bytes().all(|b| b.is_ascii_digit()) {
+        anyhow::bail!("invalid time {}", time);
+    }
+    let rfc3339 = format!(
+        "{}-{}-{}T{}:{}:{}Z",
+        &t[0..4],
+        &t[4..6],
+        &t[6..8],
+        t.get(8..10).unwrap_or("00"),
+        t.get(10..12).unwrap_or("00"),
+        t.get(12..14).unwrap_or("00")
+    );
+    Ok(humantime::parse_rfc3339(&rfc3339)?)
+}
+
+// comma separated `*` and `?` patterns, a match of a `!` pattern rejects
+fn match_pattern_list(s: &str, list: &str) -> bool {
+    let mut matched = false;
+    for pattern in list.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(pattern) if match_pattern(s.as_bytes(), pattern.as_bytes()) => return false,
+            Some(_) => {}
+            None => matched |= match_pattern(s.as_bytes(), pattern.as_bytes()),
+        }
+    }
+    matched
+}
+
+fn match_pattern(s: &[u8], pattern: &[u8]) -> bool {
+    match (pattern.first(), s.first()) {
+        (None, _) => s.is_empty(),
+        (Some(b'*'), _) => (0..=s.len()).any(|i| match_pattern(&s[i..], &pattern[1..])),
+        (Some(b'?'), Some(_)) => match_pattern(&s[1..], &pattern[1..]),
+        (Some(p), Some(c)) if p == c => match_pattern(&s[1..], &pattern[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const SK: &[u8] = include_bytes!("../../fixtures/ed25519.sk");
+    const PK: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIH6qNC6ik4skFSUo1YbBJ3sAoKlXAN9o7Yst6+hH8e23";
+    const ECDSA_PK: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBBGA4FG4Azv72sIdPDxqRRXnK9xIWuub4lwYVBNEraJe3fjZGr2aFMLCDlC4LBTah3kwDZfYQxTCT0DhECur+t8=";
+    // ssh-keygen -Y sign -f id_ed25519 -n git of "hello world\n"
+    const SIG: &str = "-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgfqo0LqKTiyQVJSjVhsEnewCgqV
+cA32jtiy3r6Efx7bcAAAADZ2l0AAAAAAAAAAZzaGE1MTIAAABTAAAAC3NzaC1lZDI1NTE5
+AAAAQC3cHJH/+sAORxxrCU4lgWEAxa/TRJDdxIWutjFmdIeSNWYloalbAprLaw1qAkhBKK
+t3ZqdNU01BYGqlpHE8qwI=
+-----END SSH SIGNATURE-----
+";
+    const MSG: &str = "hello world\n";
+    const SK_ED25519_PK: &str = "sk-ssh-ed25519@openssh.com AAAAGnNrLXNzaC1lZDI1NTE5QG9wZW5zc2guY29tAAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fAAAABHNzaDo=";
+    const SK_ECDSA_PK: &str = "sk-ecdsa-sha2-nistp256@openssh.com AAAAInNrLWVjZHNhLXNoYTItbmlzdHAyNTZAb3BlbnNzaC5jb20AAAAIbmlzdHAyNTYAAABBBByzxOVXM+yRw8MyhE+1LKkUJdUHy40f4RKVVBAr7AQ74f9vhl0ZGAHP/s5tZiCIz6pbRnLpIcR5/i91xwgFZK0AAAAEc3NoOg==";
+
+    #[test]
+    fn test_process_text_sshsig() -> Result<()> {
+        // ed25519 is deterministic, so the output matches ssh-keygen byte by byte
+        let sig = process_text_sign_sshsig(&mut MSG.as_bytes(), SK, "git")?;
+        assert_eq!(sig, SIG);
+
+        let ret = process_text_verify_sshsig(&mut MSG.as_bytes(), PK.as_bytes(), SIG, "git")?;
+        assert!(ret.verified);
+        assert_eq!(ret.namespace, "git");
+        let ret = process_text_verify_sshsig(&mut "hello".as_bytes(), PK.as_bytes(), SIG, "git")?;
+        assert!(!ret.verified);
+
+        // another namespace or key is not verified, the result tells which
+        let ret = process_text_verify_sshsig(&mut MSG.as_bytes(), PK.as_bytes(), SIG, "file")?;
+        assert!(!ret.verified);
+        assert_eq!(ret.namespace, "git");
+        let other = SigningKey::from_bytes(&[7; 32]).verifying_key();
+        let ret = process_text_verify_sshsig(&mut MSG.as_bytes(), other.as_bytes(), SIG, "git")?;
+        assert!(!ret.verified);
+        assert_eq!(
+            ret.fingerprint,
+            ed25519_fingerprint(&load_verifying_key(PK.as_bytes())?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_verify_allowed_signers() -> Result<()> {
+        let allowed_signers = format!(
+            "# team keys\n\
+             carol@example.com {ECDSA_PK}\n\
+             alice@example.com namespaces=\"git\" {PK} alice\n\
+             bob@example.com,*@corp.com,!eve@corp.com valid-before=\"20300101Z\" {PK}\n\
+             *@example.com cert-authority {PK}\n"
+        );
+        let verify = |namespace: &str, principal: Option<&str>, now: u64| {
+            let now = UNIX_EPOCH + Duration::from_secs(now);
+            process_text_verify_allowed_signers(
+                &mut MSG.as_bytes(),
+                &allowed_signers,
+                SIG,
+                namespace,
+                principal,
+                now,
+            )
+        };
+
+        let ret = verify("git", Some("alice@example.com"), 1700000000)?;
+        assert!(ret.verified);
+        assert_eq!(ret.principals, ["alice@example.com"]);
+        assert_eq!(
+            ret.fingerprint,
+            "SHA256:xcGcfmKKLU2laEc48/rVMZySnegQeVnUmo7JMM4DeSY"
+        );
+        assert_eq!(
+            verify("git", None, 1700000000)?.principals,
+            ["alice@example.com", "bob@example.com", "*@corp.com"]
+        );
+        assert!(verify("git", Some("joe@corp.com"), 1700000000)?.verified);
+        let ret = verify("git", Some("eve@corp.com"), 1700000000)?;
+        assert!(!ret.verified);
+        assert!(ret.principals.is_empty());
+        // expired
+        assert!(!verify("git", Some("joe@corp.com"), 1900000000)?.verified);
+        assert!(verify("git", Some("alice@example.com"), 1900000000)?.verified);
+        // alice may not sign files, bob may but the signature is for git
+        assert!(!verify("file", Some("alice@example.com"), 1700000000)?.verified);
+        let ret = verify("file", Some("bob@example.com"), 1700000000)?;
+        assert!(!ret.verified);
+        assert_eq!(ret.namespace, "git");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_verify_allowed_signers_mixed() -> Result<()> {
+        // security keys, broken lines, unknown options and local times, which
+        // ssh-keygen reads as local time, are skipped like ssh-keygen does
+        let allowed_signers = format!(
+            "carol@example.com {SK_ED25519_PK}\n\
+             dave@example.com namespaces=\"git\" {SK_ECDSA_PK}\n\
+             eve@example.com ssh-ed25519 AAAA\n\
+             frank@example.com no-such-option {PK}\n\
+             grace@example.com valid-before=\"20300101\" {PK}\n\
+             alice@example.com {PK}\n"
+        );
+        let verify = |principal| {
+            process_text_verify_allowed_signers(
+                &mut MSG.as_bytes(),
+                &allowed_signers,
+                SIG,
+                "git",
+                principal,
+                SystemTime::now(),
+            )
+        };
+        let ret = verify(None)?;
+        assert!(ret.verified);
+        assert_eq!(ret.principals, ["alice@example.com"]);
+        for principal in ["frank@example.com", "grace@example.com"] {
+            assert!(!verify(Some(principal))?.verified);
+        }
+        for key in [SK_ED25519_PK, SK_ECDSA_PK] {
+            assert!(parse_allowed_signer(&format!("carol@example.com {key}")).is_ok());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_pattern_list() {
+        assert!(match_pattern_list("alice@example.com", "alice@example.com"));
+        assert!(match_pattern_list("alice@example.com", "bob,*@example.com"));
+        assert!(match_pattern_list("a1", "a?"));
+        assert!(!match_pattern_list("a12", "a?"));
+        assert!(!match_pattern_list(
+            "eve@example.com",
+            "*@example.com,!eve@*"
+        ));
+        assert!(!match_pattern_list("git", "file"));
+    }
+}
@@ -1,8 +1,71 @@
-use crate::{process_genpass, TextSignFormat};
+use super::{
+    key::{ed25519_fingerprint, load_signing_key, load_verifying_key, KeyError},
+    minisign::{minisign_fingerprint, MinisignVerifier, SignifyVerifier},
+};
+use crate::{process_genbytes, process_text_key_export, KeyFormat, TextSignFormat};
+use aes_gcm::Aes256Gcm as Aes256GcmCipher;
 use anyhow::Result;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chacha20poly1305::{
+    aead::{generic_array::typenum::Unsigned, Aead, AeadCore, KeyInit, Payload},
+    XChaCha20Poly1305,
+};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
-use rand::rngs::OsRng;
-use std::{collections::HashMap, io::Read};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+// passphrase encrypted envelope: magic, version, cipher, argon2id m/t/p cost
+// (u32 be) and salt, followed by the nonce and the ciphertext. The header is
+// authenticated as associated data.
+const PBE_MAGIC: &[u8; 8] = b"rcli-pbe";
+const PBE_VERSION: u8 = 1;
+const PBE_SALT_LEN: usize = 16;
+const PBE_HEADER_LEN: usize = PBE_MAGIC.len() + 2 + 3 * 4 + PBE_SALT_LEN;
+const PBE_XCHACHA20: u8 = 1;
+const PBE_AES256GCM: u8 = 2;
+// the costs come from the unauthenticated header, so a crafted envelope
+// must not make the KDF run out of memory or time: 1 GiB, 64 passes, 16 lanes
+const PBE_MAX_M_COST: u32 = 1024 * 1024;
+const PBE_MAX_T_COST: u32 = 64;
+const PBE_MAX_P_COST: u32 = 16;
+
+// recipient encrypted envelope: magic, version and recipient count (u16 be),
+// then one stanza per recipient, an ephemeral x25519 public key and the
+// sealed file key, followed by the nonce and the xchacha20 ciphertext. The
+// header up to the last stanza is authenticated as associated data.
+const PKE_MAGIC: &[u8; 8] = b"rcli-pke";
+const PKE_VERSION: u8 = 1;
+const PKE_STANZA_LEN: usize = 32 + 24 + 32 + 16;
+const PKE_WRAP_CONTEXT: &str = "rcli 2024-03-25 text x25519 file key wrap v1";
+
+// ed25519ph signatures are Ed25519ph (RFC 8032 section 5.1) over the SHA-512
+// of the input with this context, so inputs of any size can be streamed.
+// ed25519 signatures are plain Ed25519 over the whole input.
+const ED25519PH_CONTEXT: &[u8] = b"rcli text sign v1";
+// symmetric keys are identified by a derived hash so they are not revealed
+const FINGERPRINT_CONTEXT: &str = "rcli 2024-03-25 text key fingerprint v1";
+
+/// Detached signature written by `rcli text sign --out`. Only the input is
+/// signed, `timestamp` and `comment` are informational.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignatureEnvelope {
+    pub algorithm: String,
+    /// fingerprint of the (public) key, see `process_text_key_fingerprint`
+    pub key_id: String,
+    /// unix time of signing
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// url safe base64 without padding, the same as `rcli text sign` prints
+    pub signature: String,
+}
 
 pub trait TextSigner {
     // signer could sign any input data
@@ -14,6 +77,15 @@ pub trait TextVerifier {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool>;
 }
 
+pub trait TextEncryptor {
+    // output is the random nonce followed by the ciphertext
+    fn encrypt(&self, reader: &mut dyn Read) -> Result<Vec<u8>>;
+}
+
+pub trait TextDecryptor {
+    fn decrypt(&self, reader: &mut dyn Read) -> Result<Vec<u8>>;
+}
+
 pub struct Blake3 {
     key: [u8; 32],
 }
@@ -26,21 +98,37 @@ pub struct Ed25519Verifier {
     key: VerifyingKey,
 }
 
+pub struct Ed25519PhSigner {
+    key: SigningKey,
+}
+
+pub struct Ed25519PhVerifier {
+    key: VerifyingKey,
+}
+
+pub struct XChaCha20 {
+    key: [u8; 32],
+}
+
+pub struct Aes256Gcm {
+    key: [u8; 32],
+}
+
 impl TextSigner for Blake3 {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let ret = blake3::keyed_hash(&self.key, &buf);
-        Ok(ret.as_bytes().to_vec())
+        Ok(self.hash(reader)?.as_bytes().to_vec())
     }
 }
 
 impl TextVerifier for Blake3 {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let ret = blake3::keyed_hash(&self.key, &buf);
-        Ok(ret.as_bytes() == sig)
+        let sig = <[u8; 32]>::try_from(sig).map_err(|_| KeyError::SignatureLength {
+            kind: "blake3",
+            expected: 32,
+            actual: sig.len(),
+        })?;
+        // blake3::Hash compares in constant time
+        Ok(self.hash(reader)? == blake3::Hash::from(sig))
     }
 }
 
@@ -55,20 +143,164 @@ impl TextSigner for Ed25519Signer {
 
 impl TextVerifier for Ed25519Verifier {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let signature = ed25519_signature(sig)?;
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
-        let sig = (&sig[..64]).try_into()?;
-        let signature = Signature::from_bytes(sig);
         Ok(self.key.verify(&buf, &signature).is_ok())
     }
 }
 
-impl Blake3 {
+impl TextSigner for Ed25519PhSigner {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let signature = self
+            .key
+            .sign_prehashed(prehash(reader)?, Some(ED25519PH_CONTEXT))?;
+        Ok(signature.to_bytes().to_vec())
+    }
+}
+
+impl TextVerifier for Ed25519PhVerifier {
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let signature = ed25519_signature(sig)?;
+        let prehashed = prehash(reader)?;
+        Ok(self
+            .key
+            .verify_prehashed_strict(prehashed, Some(ED25519PH_CONTEXT), &signature)
+            .is_ok())
+    }
+}
+
+fn ed25519_signature(sig: &[u8]) -> Result<Signature, KeyError> {
+    Signature::from_slice(sig).map_err(|_| KeyError::SignatureLength {
+        kind: "ed25519",
+        expected: Signature::BYTE_SIZE,
+        actual: sig.len(),
+    })
+}
+
+fn prehash(reader: &mut dyn Read) -> Result<Sha512> {
+    let mut hasher = Sha512::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher)
+}
+
+impl TextEncryptor for XChaCha20 {
+    fn encrypt(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        seal::<XChaCha20Poly1305>(&self.key, reader, b"")
+    }
+}
+
+impl TextDecryptor for XChaCha20 {
+    fn decrypt(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        open::<XChaCha20Poly1305>(&self.key, reader, b"")
+    }
+}
+
+impl TextEncryptor for Aes256Gcm {
+    fn encrypt(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        seal::<Aes256GcmCipher>(&self.key, reader, b"")
+    }
+}
+
+impl TextDecryptor for Aes256Gcm {
+    fn decrypt(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        open::<Aes256GcmCipher>(&self.key, reader, b"")
+    }
+}
+
+fn seal<C: Aead + AeadCore + KeyInit>(
+    key: &[u8; 32],
+    reader: &mut dyn Read,
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let cipher = C::new_from_slice(key)?;
+    let nonce = C::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: &buf, aad })
+        .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn open<C: Aead + AeadCore + KeyInit>(
+    key: &[u8; 32],
+    reader: &mut dyn Read,
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let nonce_len = C::NonceSize::USIZE;
+    if buf.len() < nonce_len + C::TagSize::USIZE {
+        anyhow::bail!("Ciphertext is too short");
+    }
+    let (nonce, ciphertext) = buf.split_at(nonce_len);
+    let cipher = C::new_from_slice(key)?;
+    cipher
+        .decrypt(
+            nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Decryption failed: wrong key or corrupted data"))
+}
+
+// key sealing the file key for one recipient, bound to both public keys
+fn wrap_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key(PKE_WRAP_CONTEXT);
+    hasher.update(shared);
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(recipient.as_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+fn x25519_public(key: &[u8]) -> Result<PublicKey> {
+    let key = Ed25519Verifier::try_new(key)?.key;
+    Ok(PublicKey::from(key.to_montgomery().to_bytes()))
+}
+
+fn x25519_secret(key: &[u8]) -> Result<StaticSecret> {
+    let key = Ed25519Signer::try_new(key)?.key;
+    Ok(StaticSecret::from(key.to_scalar_bytes()))
+}
+
+fn pbe_params(m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Params> {
+    if m_cost > PBE_MAX_M_COST || t_cost > PBE_MAX_T_COST || p_cost > PBE_MAX_P_COST {
+        anyhow::bail!(
+            "KDF costs m={} t={} p={} exceed the maximum m={} t={} p={}",
+            m_cost,
+            t_cost,
+            p_cost,
+            PBE_MAX_M_COST,
+            PBE_MAX_T_COST,
+            PBE_MAX_P_COST
+        );
+    }
+    Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|e| anyhow::anyhow!("Invalid KDF parameters: {}", e))
+}
+
+fn pbe_key(passphrase: &[u8], salt: &[u8], params: Params) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn aead_key(key: &[u8]) -> Result<[u8; 32], KeyError> {
+    key.try_into().map_err(|_| KeyError::KeyLength {
+        kind: "aead",
+        expected: 32,
+        actual: key.len(),
+    })
+}
+
+impl XChaCha20 {
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        // convert &[u8] to &[u8; 32]
-        let key = (&key[..32]).try_into()?;
-        Ok(Self::new(key))
+        Ok(Self::new(aead_key(key.as_ref())?))
     }
 
     pub fn new(key: [u8; 32]) -> Self {
@@ -76,42 +308,100 @@ impl Blake3 {
     }
 
     fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
         let mut map = HashMap::new();
-        map.insert("blake3.txt", key.as_bytes().to_vec());
+        map.insert("xchacha20.key", process_genbytes(&mut OsRng, 32));
         Ok(map)
     }
 }
 
-impl Ed25519Signer {
+impl Aes256Gcm {
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        Ok(Self::new(aead_key(key.as_ref())?))
+    }
+
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
+        let mut map = HashMap::new();
+        map.insert("aes256gcm.key", process_genbytes(&mut OsRng, 32));
+        Ok(map)
+    }
+}
+
+impl Blake3 {
+    // streamed keyed hash, the same as blake3::keyed_hash over the whole input
+    fn hash(&self, reader: &mut dyn Read) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        io::copy(reader, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
         let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
+        // key files saved by editors or `echo` often end with a newline, but
+        // a raw 32 byte key may end in 0x0a itself
+        let trimmed = match key.len() {
+            33 => key.strip_suffix(b"\n"),
+            34 => key.strip_suffix(b"\r\n"),
+            _ => None,
+        }
+        .unwrap_or(key);
+        let key = trimmed.try_into().map_err(|_| KeyError::KeyLength {
+            kind: "blake3",
+            expected: 32,
+            actual: trimmed.len(),
+        })?;
         Ok(Self::new(key))
     }
 
-    pub fn new(key: &[u8; 32]) -> Self {
-        let key = SigningKey::from_bytes(key);
+    pub fn new(key: [u8; 32]) -> Self {
         Self { key }
     }
 
     fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
-        let mut csprng = OsRng;
-        let sk: SigningKey = SigningKey::generate(&mut csprng);
-        let pk: VerifyingKey = (&sk).into();
+        let key = process_genbytes(&mut OsRng, 32);
         let mut map = HashMap::new();
-        map.insert("ed25519.sk", sk.to_bytes().to_vec());
-        map.insert("ed25519.pk", pk.to_bytes().to_vec());
-
+        map.insert("blake3.txt", key);
         Ok(map)
     }
 }
 
+impl Ed25519Signer {
+    /// Accepts raw, PKCS#8, OpenSSH and JWK keys.
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = load_signing_key(key.as_ref())?;
+        Ok(Self { key })
+    }
+
+    fn generate(format: KeyFormat) -> Result<HashMap<&'static str, Vec<u8>>> {
+        let mut csprng = OsRng;
+        let sk: SigningKey = SigningKey::generate(&mut csprng);
+        process_text_key_export(sk.as_bytes(), false, format)
+    }
+}
+
 impl Ed25519Verifier {
+    /// Accepts raw, SPKI, OpenSSH and JWK public keys.
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
-        let key = VerifyingKey::from_bytes(key)?;
+        let key = load_verifying_key(key.as_ref())?;
+        Ok(Self { key })
+    }
+}
+
+impl Ed25519PhSigner {
+    /// Accepts the same keys as `Ed25519Signer`.
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = load_signing_key(key.as_ref())?;
+        Ok(Self { key })
+    }
+}
+
+impl Ed25519PhVerifier {
+    /// Accepts the same keys as `Ed25519Verifier`.
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = load_verifying_key(key.as_ref())?;
         Ok(Self { key })
     }
 }
@@ -124,11 +414,59 @@ pub fn process_text_sign(
     let signer: Box<dyn TextSigner> = match format {
         TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
         TextSignFormat::Ed25519 => Box::new(Ed25519Signer::try_new(key)?),
+        TextSignFormat::Ed25519Ph => Box::new(Ed25519PhSigner::try_new(key)?),
+        TextSignFormat::Minisign => {
+            anyhow::bail!("minisign signs a trusted comment, use process_text_sign_minisign")
+        }
+        TextSignFormat::SshSig => {
+            anyhow::bail!("sshsig signs for a namespace, use process_text_sign_sshsig")
+        }
+        _ => anyhow::bail!("{} can not sign", format),
     };
 
     signer.sign(reader)
 }
 
+pub fn process_text_sign_envelope(
+    reader: &mut dyn Read,
+    key: &[u8],
+    format: TextSignFormat,
+    timestamp: u64,
+    comment: Option<String>,
+) -> Result<SignatureEnvelope> {
+    let signature = process_text_sign(reader, key, format)?;
+    let key_id = match format {
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519Ph => {
+            ed25519_fingerprint(&load_signing_key(key)?.verifying_key())
+        }
+        _ => process_text_key_fingerprint(key, format)?,
+    };
+    Ok(SignatureEnvelope {
+        algorithm: format.to_string(),
+        key_id,
+        timestamp,
+        comment,
+        signature: URL_SAFE_NO_PAD.encode(signature),
+    })
+}
+
+/// Verify with the algorithm recorded in `envelope`. Not verified if `key`
+/// is not the key the envelope names.
+pub fn process_text_verify_envelope(
+    reader: &mut dyn Read,
+    key: &[u8],
+    envelope: &SignatureEnvelope,
+) -> Result<bool> {
+    let format: TextSignFormat = envelope.algorithm.parse()?;
+    if process_text_key_fingerprint(key, format)? != envelope.key_id {
+        return Ok(false);
+    }
+    let signature = URL_SAFE_NO_PAD
+        .decode(&envelope.signature)
+        .map_err(|e| KeyError::encoding("signature", e))?;
+    process_text_verify(reader, key, &signature, format)
+}
+
 pub fn process_text_verify(
     reader: &mut dyn Read,
     key: &[u8],
@@ -138,14 +476,227 @@ pub fn process_text_verify(
     let verifier: Box<dyn TextVerifier> = match format {
         TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
         TextSignFormat::Ed25519 => Box::new(Ed25519Verifier::try_new(key)?),
+        TextSignFormat::Ed25519Ph => Box::new(Ed25519PhVerifier::try_new(key)?),
+        TextSignFormat::Minisign => Box::new(MinisignVerifier::try_new(key)?),
+        TextSignFormat::Signify => Box::new(SignifyVerifier::try_new(key)?),
+        TextSignFormat::SshSig => {
+            anyhow::bail!("sshsig signs for a namespace, use process_text_verify_sshsig")
+        }
+        _ => anyhow::bail!("{} can not verify", format),
     };
     verifier.verify(reader, sig)
 }
 
-pub fn process_text_key_generate(format: TextSignFormat) -> Result<HashMap<&'static str, Vec<u8>>> {
+/// Identify the key used to verify: the OpenSSH style `SHA256:...`
+/// fingerprint of an ed25519 public key, the minisign key id, or a hash
+/// derived from a symmetric key.
+pub fn process_text_key_fingerprint(key: &[u8], format: TextSignFormat) -> Result<String> {
+    let key = match format {
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519Ph | TextSignFormat::SshSig => {
+            return Ok(ed25519_fingerprint(&Ed25519Verifier::try_new(key)?.key))
+        }
+        TextSignFormat::Minisign | TextSignFormat::Signify => return minisign_fingerprint(key),
+        TextSignFormat::Blake3 => Blake3::try_new(key)?.key,
+        TextSignFormat::XChaCha20 | TextSignFormat::Aes256Gcm => aead_key(key)?,
+    };
+    let hash = blake3::derive_key(FINGERPRINT_CONTEXT, &key);
+    Ok(format!(
+        "{}:{}",
+        format,
+        URL_SAFE_NO_PAD.encode(&hash[..16])
+    ))
+}
+
+pub fn process_text_encrypt(
+    reader: &mut dyn Read,
+    key: &[u8],
+    format: TextSignFormat,
+) -> Result<Vec<u8>> {
+    let encryptor: Box<dyn TextEncryptor> = match format {
+        TextSignFormat::XChaCha20 => Box::new(XChaCha20::try_new(key)?),
+        TextSignFormat::Aes256Gcm => Box::new(Aes256Gcm::try_new(key)?),
+        _ => anyhow::bail!("{} can not encrypt", format),
+    };
+    encryptor.encrypt(reader)
+}
+
+pub fn process_text_decrypt(
+    reader: &mut dyn Read,
+    key: &[u8],
+    format: TextSignFormat,
+) -> Result<Vec<u8>> {
+    let decryptor: Box<dyn TextDecryptor> = match format {
+        TextSignFormat::XChaCha20 => Box::new(XChaCha20::try_new(key)?),
+        TextSignFormat::Aes256Gcm => Box::new(Aes256Gcm::try_new(key)?),
+        _ => anyhow::bail!("{} can not decrypt", format),
+    };
+    decryptor.decrypt(reader)
+}
+
+/// Encrypt with a key derived from `passphrase` by Argon2id with a random
+/// salt. The output is a self-describing envelope carrying the cipher and
+/// the KDF parameters.
+pub fn process_text_encrypt_passphrase(
+    reader: &mut dyn Read,
+    passphrase: &[u8],
+    format: TextSignFormat,
+    params: Params,
+) -> Result<Vec<u8>> {
+    let cipher_id = match format {
+        TextSignFormat::XChaCha20 => PBE_XCHACHA20,
+        TextSignFormat::Aes256Gcm => PBE_AES256GCM,
+        _ => anyhow::bail!("{} can not encrypt", format),
+    };
+    // refuse to write what decrypting would refuse to read
+    let params = pbe_params(params.m_cost(), params.t_cost(), params.p_cost())?;
+    let salt = process_genbytes(&mut OsRng, PBE_SALT_LEN);
+    let mut header = Vec::with_capacity(PBE_HEADER_LEN);
+    header.extend_from_slice(PBE_MAGIC);
+    header.extend_from_slice(&[PBE_VERSION, cipher_id]);
+    header.extend_from_slice(&params.m_cost().to_be_bytes());
+    header.extend_from_slice(&params.t_cost().to_be_bytes());
+    header.extend_from_slice(&params.p_cost().to_be_bytes());
+    header.extend_from_slice(&salt);
+
+    let key = pbe_key(passphrase, &salt, params)?;
+    let sealed = match format {
+        TextSignFormat::XChaCha20 => seal::<XChaCha20Poly1305>(&key, reader, &header)?,
+        _ => seal::<Aes256GcmCipher>(&key, reader, &header)?,
+    };
+    Ok([header, sealed].concat())
+}
+
+/// Decrypt an envelope written by `process_text_encrypt_passphrase`.
+pub fn process_text_decrypt_passphrase(
+    reader: &mut dyn Read,
+    passphrase: &[u8],
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    if buf.len() < PBE_HEADER_LEN || !buf.starts_with(PBE_MAGIC) {
+        anyhow::bail!("Not a passphrase encrypted envelope");
+    }
+    let (header, sealed) = buf.split_at(PBE_HEADER_LEN);
+    let mut fields = &header[PBE_MAGIC.len()..];
+    let mut prefix = [0u8; 2];
+    fields.read_exact(&mut prefix)?;
+    let [version, cipher_id] = prefix;
+    if version != PBE_VERSION {
+        anyhow::bail!("Unsupported envelope version {}", version);
+    }
+    if cipher_id != PBE_XCHACHA20 && cipher_id != PBE_AES256GCM {
+        anyhow::bail!("Unknown cipher {}", cipher_id);
+    }
+    let mut costs = [[0u8; 4]; 3];
+    for cost in &mut costs {
+        fields.read_exact(cost)?;
+    }
+    let [m_cost, t_cost, p_cost] = costs.map(u32::from_be_bytes);
+    let params = pbe_params(m_cost, t_cost, p_cost)?;
+    // the rest of the header
+    let salt = fields;
+
+    let key = pbe_key(passphrase, salt, params)?;
+    match cipher_id {
+        PBE_XCHACHA20 => open::<XChaCha20Poly1305>(&key, &mut &sealed[..], header),
+        _ => open::<Aes256GcmCipher>(&key, &mut &sealed[..], header),
+    }
+}
+
+/// Encrypt to the x25519 counterparts of one or more ed25519 public keys. A
+/// random file key encrypts the payload and is sealed for every recipient
+/// with a key agreed on with a fresh ephemeral key.
+pub fn process_text_encrypt_recipients(
+    reader: &mut dyn Read,
+    recipients: &[impl AsRef<[u8]>],
+) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u16::MAX as usize {
+        anyhow::bail!("Invalid number of recipients {}", recipients.len());
+    }
+    let mut file_key = [0u8; 32];
+    OsRng.fill_bytes(&mut file_key);
+    let mut header = Vec::with_capacity(PKE_MAGIC.len() + 3 + recipients.len() * PKE_STANZA_LEN);
+    header.extend_from_slice(PKE_MAGIC);
+    header.push(PKE_VERSION);
+    header.extend_from_slice(&(recipients.len() as u16).to_be_bytes());
+    for recipient in recipients {
+        let recipient = x25519_public(recipient.as_ref())?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral = PublicKey::from(&secret);
+        let shared = secret.diffie_hellman(&recipient);
+        if !shared.was_contributory() {
+            anyhow::bail!("Invalid recipient key");
+        }
+        let key = wrap_key(shared.as_bytes(), &ephemeral, &recipient);
+        header.extend_from_slice(ephemeral.as_bytes());
+        header.extend(seal::<XChaCha20Poly1305>(&key, &mut &file_key[..], b"")?);
+    }
+    let sealed = seal::<XChaCha20Poly1305>(&file_key, reader, &header)?;
+    Ok([header, sealed].concat())
+}
+
+/// Decrypt an envelope written by `process_text_encrypt_recipients` with the
+/// ed25519 signing key of one of the recipients.
+pub fn process_text_decrypt_identity(reader: &mut dyn Read, identity: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    let prefix = PKE_MAGIC.len() + 3;
+    if buf.len() < prefix || !buf.starts_with(PKE_MAGIC) {
+        anyhow::bail!("Not a recipient encrypted envelope");
+    }
+    if buf[PKE_MAGIC.len()] != PKE_VERSION {
+        anyhow::bail!("Unsupported envelope version {}", buf[PKE_MAGIC.len()]);
+    }
+    let count = u16::from_be_bytes([buf[prefix - 2], buf[prefix - 1]]) as usize;
+    let header_len = prefix + count * PKE_STANZA_LEN;
+    if buf.len() < header_len {
+        anyhow::bail!("Envelope is truncated");
+    }
+    let (header, sealed) = buf.split_at(header_len);
+
+    let secret = x25519_secret(identity)?;
+    let public = PublicKey::from(&secret);
+    let file_key = header[prefix..]
+        .chunks(PKE_STANZA_LEN)
+        .find_map(|stanza| {
+            let (ephemeral, wrapped) = stanza.split_at(32);
+            let ephemeral = PublicKey::from(<[u8; 32]>::try_from(ephemeral).ok()?);
+            let shared = secret.diffie_hellman(&ephemeral);
+            let key = wrap_key(shared.as_bytes(), &ephemeral, &public);
+            open::<XChaCha20Poly1305>(&key, &mut &wrapped[..], b"").ok()
+        })
+        .ok_or_else(|| anyhow::anyhow!("The key is not one of the recipients"))?;
+    let file_key = aead_key(&file_key)?;
+    open::<XChaCha20Poly1305>(&file_key, &mut &sealed[..], header)
+}
+
+pub fn process_text_key_generate(
+    format: TextSignFormat,
+    key_format: Option<KeyFormat>,
+) -> Result<HashMap<&'static str, Vec<u8>>> {
+    let key_format = match (format, key_format) {
+        (TextSignFormat::Ed25519 | TextSignFormat::Ed25519Ph, key_format) => {
+            key_format.unwrap_or(KeyFormat::Raw)
+        }
+        // minisign.pub next to the secret key unless asked otherwise
+        (TextSignFormat::Minisign, key_format) => key_format.unwrap_or(KeyFormat::Minisign),
+        // ssh-keygen and git expect id_ed25519 files
+        (TextSignFormat::SshSig, None | Some(KeyFormat::OpenSsh)) => KeyFormat::OpenSsh,
+        (TextSignFormat::SshSig, Some(_)) => {
+            anyhow::bail!("{} keys can only be written as openssh", format)
+        }
+        (_, None | Some(KeyFormat::Raw)) => KeyFormat::Raw,
+        (_, Some(_)) => anyhow::bail!("{} keys can only be written as raw", format),
+    };
     match format {
         TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed25519 => Ed25519Signer::generate(),
+        TextSignFormat::Ed25519
+        | TextSignFormat::Ed25519Ph
+        | TextSignFormat::Minisign
+        | TextSignFormat::SshSig => Ed25519Signer::generate(key_format),
+        TextSignFormat::Signify => anyhow::bail!("Use minisign keys to sign for signify"),
+        TextSignFormat::XChaCha20 => XChaCha20::generate(),
+        TextSignFormat::Aes256Gcm => Aes256Gcm::generate(),
     }
 }
 
@@ -155,6 +706,8 @@ mod tests {
     use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 
     const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+    const SK: &[u8] = include_bytes!("../../fixtures/ed25519.sk");
+    const PK: &[u8] = include_bytes!("../../fixtures/ed25519.pk");
 
     #[test]
     fn test_process_text_sign() -> Result<()> {
@@ -177,4 +730,317 @@ mod tests {
         assert!(ret);
         Ok(())
     }
+
+    #[test]
+    fn test_process_text_encrypt() -> Result<()> {
+        for format in [TextSignFormat::XChaCha20, TextSignFormat::Aes256Gcm] {
+            let keys = process_text_key_generate(format, None)?;
+            let key = keys.values().next().expect("one key");
+            let encrypted = process_text_encrypt(&mut "hello".as_bytes(), key, format)?;
+            let decrypted = process_text_decrypt(&mut encrypted.as_slice(), key, format)?;
+            assert_eq!(decrypted, b"hello");
+
+            // fresh nonce every time
+            let again = process_text_encrypt(&mut "hello".as_bytes(), key, format)?;
+            assert_ne!(encrypted, again);
+
+            let mut tampered = encrypted.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(process_text_decrypt(&mut tampered.as_slice(), key, format).is_err());
+            assert!(process_text_decrypt(&mut &encrypted[..8], key, format).is_err());
+            assert!(process_text_decrypt(&mut encrypted.as_slice(), &[0; 32], format).is_err());
+        }
+        assert!(
+            process_text_encrypt(&mut "hello".as_bytes(), KEY, TextSignFormat::Blake3).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_encrypt_passphrase() -> Result<()> {
+        // keep the test fast, the defaults are much more expensive
+        let params = Params::new(64, 1, 1, Some(32)).unwrap();
+        for format in [TextSignFormat::XChaCha20, TextSignFormat::Aes256Gcm] {
+            let encrypted = process_text_encrypt_passphrase(
+                &mut "hello".as_bytes(),
+                b"correct horse",
+                format,
+                params.clone(),
+            )?;
+            assert!(encrypted.starts_with(PBE_MAGIC));
+            let decrypted =
+                process_text_decrypt_passphrase(&mut encrypted.as_slice(), b"correct horse")?;
+            assert_eq!(decrypted, b"hello");
+            assert!(
+                process_text_decrypt_passphrase(&mut encrypted.as_slice(), b"wrong horse").is_err()
+            );
+
+            // the header is authenticated, e.g. the costs can not be lowered
+            let mut tampered = encrypted.clone();
+            tampered[PBE_MAGIC.len() + 9] ^= 1;
+            assert!(
+                process_text_decrypt_passphrase(&mut tampered.as_slice(), b"correct horse")
+                    .is_err()
+            );
+        }
+        assert!(process_text_decrypt_passphrase(&mut "hello".as_bytes(), b"").is_err());
+
+        // crafted costs and ciphers are rejected before the KDF runs
+        let encrypted = process_text_encrypt_passphrase(
+            &mut "hello".as_bytes(),
+            b"correct horse",
+            TextSignFormat::XChaCha20,
+            params,
+        )?;
+        let mut crafted = encrypted.clone();
+        crafted[PBE_MAGIC.len() + 2..PBE_MAGIC.len() + 6].copy_from_slice(&u32::MAX.to_be_bytes());
+        let ret = process_text_decrypt_passphrase(&mut crafted.as_slice(), b"correct horse");
+        assert!(ret.unwrap_err().to_string().contains("exceed the maximum"));
+        let mut crafted = encrypted;
+        crafted[PBE_MAGIC.len() + 1] = 3;
+        let ret = process_text_decrypt_passphrase(&mut crafted.as_slice(), b"correct horse");
+        assert_eq!(ret.unwrap_err().to_string(), "Unknown cipher 3");
+
+        let params = Params::new(PBE_MAX_M_COST + 1, 1, 1, Some(32)).unwrap();
+        let ret = process_text_encrypt_passphrase(
+            &mut "hello".as_bytes(),
+            b"correct horse",
+            TextSignFormat::XChaCha20,
+            params,
+        );
+        assert!(ret.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_encrypt_recipients() -> Result<()> {
+        let other = process_text_key_generate(TextSignFormat::Ed25519, None)?;
+        let recipients = [PK, &other["ed25519.pk"]];
+        let encrypted = process_text_encrypt_recipients(&mut "hello".as_bytes(), &recipients)?;
+        for identity in [SK, &other["ed25519.sk"]] {
+            let decrypted = process_text_decrypt_identity(&mut encrypted.as_slice(), identity)?;
+            assert_eq!(decrypted, b"hello");
+        }
+
+        let stranger = process_text_key_generate(TextSignFormat::Ed25519, None)?;
+        assert!(
+            process_text_decrypt_identity(&mut encrypted.as_slice(), &stranger["ed25519.sk"])
+                .is_err()
+        );
+
+        // dropping a recipient from the header is detected
+        let mut tampered = encrypted.clone();
+        tampered[PKE_MAGIC.len() + 2] = 1;
+        tampered
+            .drain(PKE_MAGIC.len() + 3 + PKE_STANZA_LEN..PKE_MAGIC.len() + 3 + 2 * PKE_STANZA_LEN);
+        assert!(process_text_decrypt_identity(&mut tampered.as_slice(), SK).is_err());
+
+        let none: [&[u8]; 0] = [];
+        assert!(process_text_encrypt_recipients(&mut "hello".as_bytes(), &none).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_sign_ed25519() -> Result<()> {
+        let format = TextSignFormat::Ed25519;
+        let sig = process_text_sign(&mut "hello".as_bytes(), SK, format)?;
+        // plain ed25519, as signed before ed25519ph was added
+        let plain = Signer::sign(&SigningKey::from_bytes(SK.try_into()?), b"hello");
+        assert_eq!(sig, plain.to_bytes());
+        assert!(process_text_verify(
+            &mut "hello".as_bytes(),
+            PK,
+            &sig,
+            format
+        )?);
+        assert!(!process_text_verify(
+            &mut "hallo".as_bytes(),
+            PK,
+            &sig,
+            format
+        )?);
+        assert!(process_text_verify(&mut "hello".as_bytes(), PK, &sig[..63], format).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_sign_ed25519ph() -> Result<()> {
+        let format = TextSignFormat::Ed25519Ph;
+        let input = vec![42u8; 1024 * 1024];
+        let sig = process_text_sign(&mut input.as_slice(), SK, format)?;
+        assert!(process_text_verify(
+            &mut input.as_slice(),
+            PK,
+            &sig,
+            format
+        )?);
+        assert!(!process_text_verify(&mut &input[1..], PK, &sig, format)?);
+        assert!(process_text_verify(&mut input.as_slice(), PK, &sig[..63], format).is_err());
+
+        // the two formats do not accept each other's signatures
+        let plain = process_text_sign(&mut input.as_slice(), SK, TextSignFormat::Ed25519)?;
+        assert!(!process_text_verify(
+            &mut input.as_slice(),
+            PK,
+            &plain,
+            format
+        )?);
+        assert!(!process_text_verify(
+            &mut input.as_slice(),
+            PK,
+            &sig,
+            TextSignFormat::Ed25519
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_key_errors() {
+        let err = |ret: Result<bool>| ret.unwrap_err().downcast::<KeyError>().unwrap();
+        let verify = |key: &[u8], sig: &[u8], format| {
+            process_text_verify(&mut "hello".as_bytes(), key, sig, format)
+        };
+
+        assert_eq!(
+            err(verify(&KEY[..31], &[0; 32], TextSignFormat::Blake3)),
+            KeyError::KeyLength {
+                kind: "blake3",
+                expected: 32,
+                actual: 31
+            }
+        );
+        assert_eq!(
+            err(verify(
+                &[KEY, b"x"].concat(),
+                &[0; 32],
+                TextSignFormat::Blake3
+            )),
+            KeyError::KeyLength {
+                kind: "blake3",
+                expected: 32,
+                actual: 34
+            }
+        );
+        // only the newline after a 32 byte key is dropped, not a last 0x0a
+        let key = [&KEY[..31], b"\n"].concat();
+        assert_eq!(
+            Blake3::try_new(&key).ok().map(|k| k.key),
+            key.try_into().ok()
+        );
+        assert!(Blake3::try_new([&KEY[..32], b"\r\n"].concat()).is_ok());
+        assert_eq!(
+            err(verify(
+                &[&KEY[..32], b"\r"].concat(),
+                &[0; 32],
+                TextSignFormat::Blake3
+            )),
+            KeyError::KeyLength {
+                kind: "blake3",
+                expected: 32,
+                actual: 33
+            }
+        );
+        assert_eq!(
+            err(verify(KEY, &[0; 31], TextSignFormat::Blake3)),
+            KeyError::SignatureLength {
+                kind: "blake3",
+                expected: 32,
+                actual: 31
+            }
+        );
+        assert_eq!(
+            err(verify(PK, &[0; 65], TextSignFormat::Ed25519)),
+            KeyError::SignatureLength {
+                kind: "ed25519",
+                expected: 64,
+                actual: 65
+            }
+        );
+        assert_eq!(
+            err(verify(&SK[..16], &[0; 64], TextSignFormat::Ed25519)),
+            KeyError::KeyLength {
+                kind: "ed25519",
+                expected: 32,
+                actual: 16
+            }
+        );
+
+        let ret = process_text_sign(&mut "hello".as_bytes(), &SK[..16], TextSignFormat::Ed25519);
+        assert!(ret.unwrap_err().downcast_ref::<KeyError>().is_some());
+        let ret = process_text_encrypt(&mut "hello".as_bytes(), KEY, TextSignFormat::XChaCha20);
+        assert!(ret.unwrap_err().downcast_ref::<KeyError>().is_some());
+    }
+
+    #[test]
+    fn test_process_text_key_generate() -> Result<()> {
+        let keys = process_text_key_generate(TextSignFormat::Minisign, None)?;
+        assert!(keys.contains_key("minisign.pub"));
+        let keys = process_text_key_generate(TextSignFormat::Minisign, Some(KeyFormat::Raw))?;
+        assert!(keys.contains_key("ed25519.pk"));
+        let keys = process_text_key_generate(TextSignFormat::Ed25519, None)?;
+        assert!(keys.contains_key("ed25519.pk"));
+
+        let ret = process_text_key_generate(TextSignFormat::Blake3, Some(KeyFormat::Pem));
+        assert!(ret.is_err());
+        let ret = process_text_key_generate(TextSignFormat::SshSig, Some(KeyFormat::Raw));
+        assert!(ret.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_key_fingerprint() -> Result<()> {
+        let fingerprint = process_text_key_fingerprint(PK, TextSignFormat::Ed25519)?;
+        assert!(fingerprint.starts_with("SHA256:"));
+
+        let fingerprint = process_text_key_fingerprint(KEY, TextSignFormat::Blake3)?;
+        assert!(fingerprint.starts_with("blake3:"));
+        assert_eq!(fingerprint.len(), "blake3:".len() + 22);
+        assert!(!fingerprint.contains(&URL_SAFE_NO_PAD.encode(&KEY[..16])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_text_sign_envelope() -> Result<()> {
+        for (sk, pk, format) in [
+            (SK, PK, TextSignFormat::Ed25519),
+            (KEY, KEY, TextSignFormat::Blake3),
+        ] {
+            let comment = Some("release 1.0".to_string());
+            let envelope = process_text_sign_envelope(
+                &mut "hello".as_bytes(),
+                sk,
+                format,
+                1711324800,
+                comment,
+            )?;
+            assert_eq!(envelope.algorithm, format.to_string());
+            assert_eq!(envelope.key_id, process_text_key_fingerprint(pk, format)?);
+
+            let json = serde_json::to_string(&envelope)?;
+            let envelope: SignatureEnvelope = serde_json::from_str(&json)?;
+            assert!(process_text_verify_envelope(
+                &mut "hello".as_bytes(),
+                pk,
+                &envelope
+            )?);
+            assert!(!process_text_verify_envelope(
+                &mut "hallo".as_bytes(),
+                pk,
+                &envelope
+            )?);
+        }
+
+        let envelope = process_text_sign_envelope(
+            &mut "hello".as_bytes(),
+            SK,
+            TextSignFormat::Ed25519,
+            0,
+            None,
+        )?;
+        let other = process_text_key_generate(TextSignFormat::Ed25519, None)?;
+        let ret =
+            process_text_verify_envelope(&mut "hello".as_bytes(), &other["ed25519.pk"], &envelope);
+        assert!(!ret?);
+        Ok(())
+    }
 }
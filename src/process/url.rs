@@ -0,0 +1,106 @@
+use crate::{read_text, UrlEncodeMode};
+use anyhow::Result;
+use percent_encoding::{percent_decode, utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+use std::io::Read;
+
+// RFC 3986 unreserved characters stay as is, everything else is escaped
+pub(crate) const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// same as javascript's encodeURI: reserved characters keep their meaning
+const URL: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+// application/x-www-form-urlencoded byte serializer (WHATWG URL standard)
+const FORM: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'*')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_');
+
+/// Percent-encode the text from `reader`. A trailing newline is ignored.
+pub fn process_url_encode(reader: &mut dyn Read, mode: UrlEncodeMode) -> Result<String> {
+    let input = read_text(reader)?;
+    let encoded = match mode {
+        UrlEncodeMode::Component => utf8_percent_encode(&input, COMPONENT).to_string(),
+        UrlEncodeMode::Url => utf8_percent_encode(&input, URL).to_string(),
+        UrlEncodeMode::Form => utf8_percent_encode(&input, FORM)
+            .to_string()
+            .replace("%20", "+"),
+    };
+    Ok(encoded)
+}
+
+/// Decode percent-encoded text from `reader`. A trailing newline is ignored.
+pub fn process_url_decode(reader: &mut dyn Read, mode: UrlEncodeMode) -> Result<String> {
+    let input = read_text(reader)?;
+    let input = match mode {
+        UrlEncodeMode::Form => input.replace('+', " "),
+        UrlEncodeMode::Component | UrlEncodeMode::Url => input,
+    };
+    let decoded = percent_decode(input.as_bytes()).decode_utf8()?;
+    Ok(decoded.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "https://example.com/a b?q=rust & c++&name=陈#top\n";
+
+    #[test]
+    fn test_process_url_encode() -> Result<()> {
+        assert_eq!(
+            process_url_encode(&mut INPUT.as_bytes(), UrlEncodeMode::Component)?,
+            "https%3A%2F%2Fexample.com%2Fa%20b%3Fq%3Drust%20%26%20c%2B%2B%26name%3D%E9%99%88%23top"
+        );
+        assert_eq!(
+            process_url_encode(&mut INPUT.as_bytes(), UrlEncodeMode::Url)?,
+            "https://example.com/a%20b?q=rust%20&%20c++&name=%E9%99%88#top"
+        );
+        assert_eq!(
+            process_url_encode(&mut "a b&c=d*~".as_bytes(), UrlEncodeMode::Form)?,
+            "a+b%26c%3Dd*%7E"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_url_decode() -> Result<()> {
+        for mode in [
+            UrlEncodeMode::Component,
+            UrlEncodeMode::Url,
+            UrlEncodeMode::Form,
+        ] {
+            let encoded = process_url_encode(&mut INPUT.as_bytes(), mode)?;
+            let decoded = process_url_decode(&mut encoded.as_bytes(), mode)?;
+            assert_eq!(decoded, INPUT.trim_end());
+        }
+        // '+' is only a space in forms
+        assert_eq!(
+            process_url_decode(&mut "c++%20x".as_bytes(), UrlEncodeMode::Component)?,
+            "c++ x"
+        );
+        assert_eq!(
+            process_url_decode(&mut "c++%20x".as_bytes(), UrlEncodeMode::Form)?,
+            "c   x"
+        );
+        assert!(process_url_decode(&mut "%ff".as_bytes(), UrlEncodeMode::Component).is_err());
+        Ok(())
+    }
+}